@@ -0,0 +1,212 @@
+//! Drives `CustomRc`'s raw-pointer API (`into_raw`, `from_raw` and the
+//! strong-count adjusters) over payloads of every size and alignment the
+//! offset math has to get right, checking values and counts after each step.
+//!
+//! `cargo +nightly fuzz run raw_round_trip` from the repository root.
+
+#![no_main]
+
+use std::fmt::Debug;
+use std::mem::ManuallyDrop;
+
+use libfuzzer_sys::fuzz_target;
+use rc_playground::CustomRc;
+
+/// A value made from one input byte, so a slot knows what it should read.
+trait Payload: Clone + PartialEq + Debug {
+    fn from_byte(byte: u8) -> Self;
+}
+
+impl Payload for () {
+    fn from_byte(_: u8) -> Self {}
+}
+
+impl Payload for u8 {
+    fn from_byte(byte: u8) -> Self {
+        byte
+    }
+}
+
+impl Payload for u16 {
+    fn from_byte(byte: u8) -> Self {
+        u16::from(byte) * 257
+    }
+}
+
+impl Payload for u64 {
+    fn from_byte(byte: u8) -> Self {
+        u64::from(byte) * 0x0101_0101_0101_0101
+    }
+}
+
+impl Payload for u128 {
+    fn from_byte(byte: u8) -> Self {
+        u128::from(byte) << 120 | u128::from(byte)
+    }
+}
+
+impl Payload for [u8; 3] {
+    fn from_byte(byte: u8) -> Self {
+        [byte, !byte, byte.wrapping_add(1)]
+    }
+}
+
+impl Payload for (u8, u64) {
+    fn from_byte(byte: u8) -> Self {
+        (byte, u64::from(byte) << 32)
+    }
+}
+
+/// Aligned past anything the counts are, so the value is padded away from
+/// them.
+#[derive(Clone, PartialEq, Debug)]
+#[repr(align(64))]
+struct Overaligned([u8; 5]);
+
+impl Payload for Overaligned {
+    fn from_byte(byte: u8) -> Self {
+        Overaligned([byte; 5])
+    }
+}
+
+/// Owns heap memory of its own, so a double drop or a leak shows up.
+impl Payload for String {
+    fn from_byte(byte: u8) -> Self {
+        byte.to_string()
+    }
+}
+
+/// One allocation and every handle to it, held either as a `CustomRc` or
+/// as a pointer from `into_raw`. Its strong count must be the sum.
+struct Slot<T> {
+    value: T,
+    held: Vec<CustomRc<T>>,
+    raw: Vec<*const T>,
+}
+
+impl<T: Payload> Slot<T> {
+    fn check(&self) {
+        let rc = match (self.held.first(), self.raw.first()) {
+            (Some(rc), _) => ManuallyDrop::new(CustomRc::clone(rc)),
+            (None, Some(&ptr)) => {
+                unsafe { CustomRc::increment_strong_count(ptr) };
+                ManuallyDrop::new(unsafe { CustomRc::from_raw(ptr) })
+            }
+            (None, None) => return,
+        };
+        assert_eq!(**rc, self.value);
+        // Less the handle just made to look.
+        assert_eq!(
+            CustomRc::strong_count(&rc) - 1,
+            self.held.len() + self.raw.len()
+        );
+        drop(ManuallyDrop::into_inner(rc));
+        for &ptr in &self.raw {
+            assert_eq!(unsafe { &*ptr }, &self.value);
+        }
+    }
+}
+
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        for ptr in self.raw.drain(..) {
+            unsafe { CustomRc::decrement_strong_count(ptr) };
+        }
+    }
+}
+
+/// Every live allocation of one payload type.
+struct Pool<T> {
+    slots: Vec<Slot<T>>,
+}
+
+impl<T: Payload> Pool<T> {
+    fn new() -> Self {
+        Pool { slots: Vec::new() }
+    }
+
+    fn step(&mut self, op: u8, arg: u8) {
+        if op == 0 || self.slots.is_empty() {
+            let value = T::from_byte(arg);
+            self.slots.push(Slot {
+                held: vec![CustomRc::new(value.clone())],
+                value,
+                raw: Vec::new(),
+            });
+            return;
+        }
+        let index = usize::from(arg) % self.slots.len();
+        let slot = &mut self.slots[index];
+        match op {
+            // Clone a held handle.
+            1 => {
+                if let Some(rc) = slot.held.first() {
+                    slot.held.push(CustomRc::clone(rc));
+                }
+            }
+            // Give a held handle up as a pointer.
+            2 => {
+                if let Some(rc) = slot.held.pop() {
+                    slot.raw.push(CustomRc::into_raw(rc));
+                }
+            }
+            // Take a pointer back.
+            3 => {
+                if let Some(ptr) = slot.raw.pop() {
+                    slot.held.push(unsafe { CustomRc::from_raw(ptr) });
+                }
+            }
+            // Share a pointer without taking it back.
+            4 => {
+                if let Some(&ptr) = slot.raw.first() {
+                    unsafe { CustomRc::increment_strong_count(ptr) };
+                    slot.raw.push(ptr);
+                }
+            }
+            // Release a pointer without taking it back.
+            5 => {
+                if let Some(ptr) = slot.raw.pop() {
+                    unsafe { CustomRc::decrement_strong_count(ptr) };
+                }
+            }
+            // Drop a held handle.
+            _ => {
+                slot.held.pop();
+            }
+        }
+        if slot.held.is_empty() && slot.raw.is_empty() {
+            self.slots.swap_remove(index);
+        } else {
+            slot.check();
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut pools = (
+        Pool::<()>::new(),
+        Pool::<u8>::new(),
+        Pool::<u16>::new(),
+        Pool::<u64>::new(),
+        Pool::<u128>::new(),
+        Pool::<[u8; 3]>::new(),
+        Pool::<(u8, u64)>::new(),
+        Pool::<Overaligned>::new(),
+        Pool::<String>::new(),
+    );
+    for step in data.chunks_exact(2) {
+        // The low three bits pick the operation, the rest the payload type.
+        let (op, arg) = (step[0] & 7, step[1]);
+        match (step[0] >> 3) % 9 {
+            0 => pools.0.step(op, arg),
+            1 => pools.1.step(op, arg),
+            2 => pools.2.step(op, arg),
+            3 => pools.3.step(op, arg),
+            4 => pools.4.step(op, arg),
+            5 => pools.5.step(op, arg),
+            6 => pools.6.step(op, arg),
+            7 => pools.7.step(op, arg),
+            _ => pools.8.step(op, arg),
+        }
+    }
+});
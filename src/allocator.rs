@@ -0,0 +1,413 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crate::error::BenchError;
+
+/// Alignment of the bump arena block itself.
+const ARENA_ALIGN: usize = 4096;
+
+/// Number of power-of-two buckets in a `SizeHistogram`: bucket `i` holds the
+/// sizes in `(2^(i-1), 2^i]`, which covers every size a `Layout` allows.
+pub const SIZE_BUCKETS: usize = usize::BITS as usize;
+
+/// Most blocks allocated inside `assert_no_leaks` that can be live at once;
+/// past that, the extra ones are all counted as leaks.
+pub const LEAK_CHECK_SLOTS: usize = 1024;
+
+/// A global allocator that forwards to `System`, except while a bump arena is
+/// active: allocations are then carved out of one pre-reserved block and
+/// frees are no-ops until the arena is released with `end_bump`.
+///
+/// Once the arena is full, allocations quietly fall back to `System`. The
+/// arena is meant to be driven from a single thread (the benchmark's).
+///
+/// Between `begin_size_tracking` and `end_size_tracking` it also counts every
+/// allocation into a `SizeHistogram`, and every free, so leaks show up.
+/// Inside `assert_no_leaks` it remembers the address of every block
+/// allocated, until it is freed again.
+#[derive(Debug)]
+pub struct BenchAllocator {
+    arena: AtomicPtr<u8>,
+    arena_capacity: AtomicUsize,
+    arena_used: AtomicUsize,
+    tracking: AtomicBool,
+    size_counts: [AtomicUsize; SIZE_BUCKETS],
+    frees: AtomicUsize,
+    leak_checking: AtomicBool,
+    /// Addresses of the blocks `assert_no_leaks` saw allocated and not yet
+    /// freed; 0 marks a free slot.
+    leak_slots: [AtomicUsize; LEAK_CHECK_SLOTS],
+    /// Blocks allocated while every slot was taken.
+    leak_overflow: AtomicUsize,
+}
+
+impl Default for BenchAllocator {
+    fn default() -> Self {
+        BenchAllocator::new()
+    }
+}
+
+impl BenchAllocator {
+    pub const fn new() -> Self {
+        BenchAllocator {
+            arena: AtomicPtr::new(ptr::null_mut()),
+            arena_capacity: AtomicUsize::new(0),
+            arena_used: AtomicUsize::new(0),
+            tracking: AtomicBool::new(false),
+            size_counts: [const { AtomicUsize::new(0) }; SIZE_BUCKETS],
+            frees: AtomicUsize::new(0),
+            leak_checking: AtomicBool::new(false),
+            leak_slots: [const { AtomicUsize::new(0) }; LEAK_CHECK_SLOTS],
+            leak_overflow: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts counting allocation sizes from zero.
+    pub fn begin_size_tracking(&self) {
+        for count in &self.size_counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.frees.store(0, Ordering::Relaxed);
+        self.tracking.store(true, Ordering::Relaxed);
+    }
+
+    /// Allocations made minus allocations freed since `begin_size_tracking`
+    /// (until `end_size_tracking`): zero if everything allocated in between
+    /// was freed again, negative if older allocations were freed too.
+    pub fn tracked_live_allocations(&self) -> isize {
+        let made: usize = self
+            .size_counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum();
+        made as isize - self.frees.load(Ordering::Relaxed) as isize
+    }
+
+    /// Stops counting and returns the sizes seen since `begin_size_tracking`.
+    pub fn end_size_tracking(&self) -> SizeHistogram {
+        self.tracking.store(false, Ordering::Relaxed);
+        SizeHistogram {
+            counts: self
+                .size_counts
+                .each_ref()
+                .map(|count| count.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Runs `f` and panics if any block it allocated is still live when it
+    /// returns, so that a leaked `CustomRcInner` (say, through a bad
+    /// `try_unwrap` or weak drop) fails a check instead of passing. `f` has
+    /// to drop everything it builds. Freeing older allocations is fine, and
+    /// doesn't make up for a leak: only the blocks allocated inside `f` are
+    /// followed, by address.
+    ///
+    /// It can't nest, and it sees every thread's allocations.
+    pub fn assert_no_leaks(&self, f: impl FnOnce()) {
+        /// Stops following blocks even if `f` panics.
+        struct Stop<'a>(&'a BenchAllocator);
+
+        impl Drop for Stop<'_> {
+            fn drop(&mut self) {
+                self.0.leak_checking.store(false, Ordering::SeqCst);
+            }
+        }
+
+        for slot in &self.leak_slots {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.leak_overflow.store(0, Ordering::Relaxed);
+        self.leak_checking.store(true, Ordering::SeqCst);
+        let stop = Stop(self);
+        f();
+        drop(stop);
+        let leaked = self
+            .leak_slots
+            .iter()
+            .filter(|slot| slot.load(Ordering::Relaxed) != 0)
+            .count()
+            + self.leak_overflow.load(Ordering::Relaxed);
+        assert!(leaked == 0, "{} allocation(s) leaked", leaked);
+    }
+
+    /// Follows `ptr` if `assert_no_leaks` is running.
+    fn remember(&self, ptr: *mut u8) {
+        if ptr.is_null() || !self.leak_checking.load(Ordering::Relaxed) {
+            return;
+        }
+        let taken = self.leak_slots.iter().any(|slot| {
+            slot.compare_exchange(0, ptr as usize, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        });
+        if !taken {
+            self.leak_overflow.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops following `ptr`, if it was followed at all.
+    fn forget(&self, ptr: *mut u8) {
+        if !self.leak_checking.load(Ordering::Relaxed) {
+            return;
+        }
+        let ptr = ptr as usize;
+        if let Some(slot) = self
+            .leak_slots
+            .iter()
+            .find(|slot| slot.load(Ordering::Relaxed) == ptr)
+        {
+            let _ = slot.compare_exchange(ptr, 0, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    fn record_size(&self, size: usize) {
+        if self.tracking.load(Ordering::Relaxed) {
+            self.size_counts[size_bucket(size)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record_free(&self) {
+        if self.tracking.load(Ordering::Relaxed) {
+            self.frees.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Starts serving allocations from a fresh `capacity`-byte bump arena.
+    pub fn begin_bump(&self, capacity: usize) -> Result<(), BenchError> {
+        let layout =
+            Layout::from_size_align(capacity, ARENA_ALIGN).map_err(|_| BenchError::AllocFailed)?;
+        let arena = unsafe { System.alloc(layout) };
+        if arena.is_null() {
+            return Err(BenchError::AllocFailed);
+        }
+        self.arena_used.store(0, Ordering::Relaxed);
+        self.arena_capacity.store(capacity, Ordering::Relaxed);
+        self.arena.store(arena, Ordering::Release);
+        Ok(())
+    }
+
+    /// Stops bump allocation and hands the arena back to the system. Every
+    /// object allocated from the arena must already be dropped.
+    pub fn end_bump(&self) {
+        let arena = self.arena.swap(ptr::null_mut(), Ordering::AcqRel);
+        if arena.is_null() {
+            return;
+        }
+        let capacity = self.arena_capacity.swap(0, Ordering::Relaxed);
+        unsafe {
+            System.dealloc(
+                arena,
+                Layout::from_size_align_unchecked(capacity, ARENA_ALIGN),
+            )
+        };
+    }
+
+    fn in_arena(&self, ptr: *mut u8) -> bool {
+        let arena = self.arena.load(Ordering::Acquire);
+        !arena.is_null()
+            && (ptr as usize).wrapping_sub(arena as usize)
+                < self.arena_capacity.load(Ordering::Relaxed)
+    }
+
+    /// Bumps `layout` out of the active arena, or returns null if there is no
+    /// arena or it's full.
+    fn bump(&self, layout: Layout) -> *mut u8 {
+        let arena = self.arena.load(Ordering::Acquire);
+        if arena.is_null() {
+            return ptr::null_mut();
+        }
+        let capacity = self.arena_capacity.load(Ordering::Relaxed);
+        let mut used = self.arena_used.load(Ordering::Relaxed);
+        loop {
+            // The arena base is page-aligned, so aligning the offset aligns the pointer.
+            let offset = (used + layout.align() - 1) & !(layout.align() - 1);
+            let end = match offset.checked_add(layout.size()) {
+                Some(end) if end <= capacity => end,
+                _ => return ptr::null_mut(),
+            };
+            match self.arena_used.compare_exchange_weak(
+                used,
+                end,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return unsafe { arena.add(offset) },
+                Err(actual) => used = actual,
+            }
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for BenchAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.record_size(layout.size());
+        let mut ptr = self.bump(layout);
+        if ptr.is_null() {
+            ptr = System.alloc(layout);
+        }
+        self.remember(ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.record_free();
+        self.forget(ptr);
+        if self.in_arena(ptr) {
+            return;
+        }
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if self.arena.load(Ordering::Acquire).is_null() {
+            // Counted as freeing the old block for a new one.
+            self.record_free();
+            self.record_size(new_size);
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                self.forget(ptr);
+                self.remember(new_ptr);
+            }
+            return new_ptr;
+        }
+        // Either side may live in the arena, so move the block by hand
+        // (`alloc` records the new size).
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+// ========================
+// Allocation Size Histogram
+// ========================
+
+/// The bucket of `size`: the exponent of the smallest power of two >= it.
+fn size_bucket(size: usize) -> usize {
+    (usize::BITS - size.saturating_sub(1).leading_zeros()) as usize
+}
+
+/// How many allocations of each power-of-two size class were made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeHistogram {
+    counts: [usize; SIZE_BUCKETS],
+}
+
+impl SizeHistogram {
+    /// `(largest size in the bucket, allocations)` for every non-empty
+    /// bucket, smallest sizes first.
+    pub fn buckets(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(bucket, &count)| (1 << bucket, count))
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+impl fmt::Display for SizeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (size, count) in self.buckets() {
+            writeln!(f, "{:>12} B  {}", format!("<= {}", size), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_fall_into_power_of_two_buckets() {
+        assert_eq!(size_bucket(0), 0);
+        assert_eq!(size_bucket(1), 0);
+        assert_eq!(size_bucket(2), 1);
+        assert_eq!(size_bucket(3), 2);
+        assert_eq!(size_bucket(32), 5);
+        assert_eq!(size_bucket(33), 6);
+        assert_eq!(size_bucket(usize::MAX), SIZE_BUCKETS);
+    }
+
+    #[test]
+    fn tracking_counts_sizes_between_begin_and_end() {
+        let allocator = BenchAllocator::new();
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let large = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let before = allocator.alloc(small);
+            allocator.begin_size_tracking();
+            let a = allocator.alloc(small);
+            let b = allocator.alloc(small);
+            let c = allocator.alloc(large);
+            allocator.dealloc(a, small);
+            let histogram = allocator.end_size_tracking();
+            allocator.dealloc(b, small);
+            allocator.dealloc(c, large);
+            allocator.dealloc(before, small);
+
+            assert_eq!(histogram.buckets().collect::<Vec<_>>(), [(32, 2), (128, 1)]);
+            assert_eq!(histogram.total(), 3);
+            assert_eq!(
+                histogram.to_string(),
+                "       <= 32 B  2\n      <= 128 B  1\n"
+            );
+        }
+    }
+
+    #[test]
+    fn assert_no_leaks_catches_a_block_left_allocated() {
+        let allocator = BenchAllocator::new();
+        let layout = Layout::new::<[usize; 4]>();
+        let mut leaked = ptr::null_mut();
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            allocator.assert_no_leaks(|| leaked = unsafe { allocator.alloc(layout) });
+        }));
+        let message = caught.err().unwrap();
+        assert_eq!(
+            message.downcast_ref::<String>().map(String::as_str),
+            Some("1 allocation(s) leaked")
+        );
+        unsafe { allocator.dealloc(leaked, layout) };
+    }
+
+    #[test]
+    fn assert_no_leaks_accepts_freeing_what_came_before() {
+        let allocator = BenchAllocator::new();
+        let layout = Layout::new::<[usize; 4]>();
+        let older = unsafe { allocator.alloc(layout) };
+        allocator.assert_no_leaks(|| unsafe {
+            let block = allocator.alloc(layout);
+            allocator.dealloc(block, layout);
+            allocator.dealloc(older, layout);
+        });
+    }
+
+    #[test]
+    fn freeing_an_older_block_does_not_hide_a_leak() {
+        let allocator = BenchAllocator::new();
+        let layout = Layout::new::<[usize; 4]>();
+        let older = unsafe { allocator.alloc(layout) };
+        let mut leaked = ptr::null_mut();
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            allocator.assert_no_leaks(|| unsafe {
+                leaked = allocator.alloc(layout);
+                allocator.dealloc(older, layout);
+            });
+        }));
+        let message = caught.err().unwrap();
+        assert_eq!(
+            message.downcast_ref::<String>().map(String::as_str),
+            Some("1 allocation(s) leaked")
+        );
+        unsafe { allocator.dealloc(leaked, layout) };
+    }
+}
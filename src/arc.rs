@@ -0,0 +1,474 @@
+use std::cell::{Cell, UnsafeCell};
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::entity::Entity;
+use crate::rc::{Constructor, CountedRc};
+
+// ========================
+// Implement an Atomic CustomArc
+// ========================
+
+/// Where a `CustomArc` keeps its count.
+pub trait ArcCount {
+    fn new(count: usize) -> Self;
+    fn count(&self) -> &AtomicUsize;
+}
+
+impl ArcCount for AtomicUsize {
+    fn new(count: usize) -> Self {
+        AtomicUsize::new(count)
+    }
+
+    fn count(&self) -> &AtomicUsize {
+        self
+    }
+}
+
+/// A count with a cache line to itself: neither the value behind it nor a
+/// neighbouring allocation can share the line, so threads working on
+/// adjacent handles don't false-share.
+#[repr(align(64))]
+pub struct PaddedCount(AtomicUsize);
+
+impl ArcCount for PaddedCount {
+    fn new(count: usize) -> Self {
+        PaddedCount(AtomicUsize::new(count))
+    }
+
+    fn count(&self) -> &AtomicUsize {
+        &self.0
+    }
+}
+
+struct CustomArcInner<T, C> {
+    ref_count: C,
+    value: T,
+}
+
+/// The thread-safe counterpart of `CustomRc`: same layout, but the count is
+/// updated with atomic read-modify-write operations.
+pub struct CustomArc<T, C: ArcCount = AtomicUsize> {
+    ptr: NonNull<CustomArcInner<T, C>>,
+}
+
+/// A `CustomArc` whose count sits on its own cache line.
+pub type PaddedArc<T> = CustomArc<T, PaddedCount>;
+
+unsafe impl<T: Send + Sync, C: ArcCount> Send for CustomArc<T, C> {}
+unsafe impl<T: Send + Sync, C: ArcCount> Sync for CustomArc<T, C> {}
+
+impl<T> CustomArc<T> {
+    /// Creates a new CustomArc instance.
+    pub fn new(value: T) -> Self {
+        CustomArc::with_count(value)
+    }
+}
+
+impl<T, C: ArcCount> CustomArc<T, C> {
+    /// Creates a new CustomArc that stores its count as a `C`.
+    pub fn with_count(value: T) -> Self {
+        let boxed = Box::new(CustomArcInner {
+            ref_count: C::new(1),
+            value,
+        });
+        CustomArc {
+            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(boxed)) },
+        }
+    }
+
+    fn count(&self) -> &AtomicUsize {
+        unsafe { self.ptr.as_ref() }.ref_count.count()
+    }
+}
+
+impl<T, C: ArcCount> Clone for CustomArc<T, C> {
+    fn clone(&self) -> Self {
+        // Relaxed is enough: the new handle is derived from an existing one,
+        // so the allocation can't be freed concurrently.
+        self.count().fetch_add(1, Ordering::Relaxed);
+        CustomArc { ptr: self.ptr }
+    }
+}
+
+impl<T, C: ArcCount> Deref for CustomArc<T, C> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.ptr.as_ref().value }
+    }
+}
+
+impl<T, C: ArcCount> Drop for CustomArc<T, C> {
+    fn drop(&mut self) {
+        if self.count().fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        // Synchronize with every other handle's release before freeing.
+        fence(Ordering::Acquire);
+        unsafe {
+            let _ = Box::from_raw(self.ptr.as_ptr());
+        }
+    }
+}
+
+impl<T, C: ArcCount> Constructor<T> for CustomArc<T, C> {
+    fn new(value: T) -> Self {
+        CustomArc::with_count(value)
+    }
+}
+
+impl<C: ArcCount> CountedRc<Entity> for CustomArc<Entity, C> {
+    fn strong_count(&self) -> usize {
+        self.count().load(Ordering::Acquire)
+    }
+}
+
+// ========================
+// Implement a Biased BiasedArc
+// ========================
+
+/// Set in `BiasedArcInner::shared` once the owner has folded its biased count
+/// into the shared one. The live count never gets anywhere near this bit, so
+/// `shared >= MERGED / 2` reliably means "merged".
+const MERGED: isize = 1 << (isize::BITS - 2);
+
+fn is_merged(shared: isize) -> bool {
+    shared >= MERGED / 2
+}
+
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A cheap, never-reused id for the calling thread. `thread::current()` would
+/// bump an `Arc` on every call, which defeats the point of the biased path.
+fn current_thread_id() -> usize {
+    THREAD_ID.with(|id| {
+        let mut current = id.get();
+        if current == 0 {
+            current = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+            id.set(current);
+        }
+        current
+    })
+}
+
+/// An allocation whose biased count went negative, waiting for its owner
+/// thread to merge it. The entry holds one reference to the allocation.
+struct QueuedMerge {
+    owner: usize,
+    ptr: *const (),
+    merge: unsafe fn(*const ()),
+}
+
+unsafe impl Send for QueuedMerge {}
+
+static MERGE_QUEUE: Mutex<Vec<QueuedMerge>> = Mutex::new(Vec::new());
+
+struct BiasedArcInner<T> {
+    owner: usize,
+    /// Clones minus drops performed on the owner thread. Only ever touched
+    /// from the owner thread, so it's updated without atomics.
+    biased: UnsafeCell<usize>,
+    /// Clones minus drops performed on every other thread (may go negative
+    /// while handles cloned by the owner are dropped elsewhere), plus `MERGED`.
+    shared: AtomicIsize,
+    /// Whether a reference has been handed to the owner's merge queue.
+    queued: AtomicBool,
+    value: T,
+}
+
+/// A prototype of biased reference counting: the thread that created the
+/// allocation clones and drops with plain increments, every other thread
+/// falls back to atomics on a second counter.
+///
+/// When the owner's count reaches zero it merges the two counters and the
+/// allocation behaves like a `CustomArc` from then on. If the owner's handles
+/// all end up dropped on other threads instead, the allocation is queued for
+/// its owner, which must call `BiasedArc::merge_queued` to reclaim it; until
+/// then (or forever, if the owner has exited) the allocation is leaked.
+pub struct BiasedArc<T> {
+    ptr: NonNull<BiasedArcInner<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for BiasedArc<T> {}
+unsafe impl<T: Send + Sync> Sync for BiasedArc<T> {}
+
+impl<T> BiasedArc<T> {
+    /// Creates a new BiasedArc owned by the calling thread.
+    pub fn new(value: T) -> Self {
+        let boxed = Box::new(BiasedArcInner {
+            owner: current_thread_id(),
+            biased: UnsafeCell::new(1),
+            shared: AtomicIsize::new(0),
+            queued: AtomicBool::new(false),
+            value,
+        });
+        BiasedArc {
+            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(boxed)) },
+        }
+    }
+
+    /// Merges every allocation queued for the calling thread, freeing those
+    /// that are no longer referenced.
+    pub fn merge_queued() {
+        let owner = current_thread_id();
+        let mine: Vec<QueuedMerge> = {
+            let mut queue = MERGE_QUEUE.lock().unwrap();
+            let (mine, others) = queue.drain(..).partition(|entry| entry.owner == owner);
+            *queue = others;
+            mine
+        };
+        // Run the merges outside the lock: freeing a value may drop other
+        // BiasedArcs, which may need to queue themselves.
+        for entry in mine {
+            unsafe { (entry.merge)(entry.ptr) };
+        }
+    }
+
+    fn inner(&self) -> &BiasedArcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Whether the calling thread may use the non-atomic biased count.
+    fn on_biased_path(&self) -> bool {
+        let inner = self.inner();
+        // Only the owner ever sets `MERGED`, so a relaxed load sees its own write.
+        inner.owner == current_thread_id() && !is_merged(inner.shared.load(Ordering::Relaxed))
+    }
+
+    /// Drops one reference from the shared count of a merged allocation.
+    unsafe fn release_shared(ptr: NonNull<BiasedArcInner<T>>) {
+        if ptr.as_ref().shared.fetch_sub(1, Ordering::Release) != MERGED + 1 {
+            return;
+        }
+        fence(Ordering::Acquire);
+        let _ = Box::from_raw(ptr.as_ptr());
+    }
+
+    /// Queue entry callback, run on the owner thread.
+    unsafe fn merge_queued_entry(ptr: *const ()) {
+        let ptr = NonNull::new_unchecked(ptr as *mut BiasedArcInner<T>);
+        let inner = ptr.as_ref();
+        if !is_merged(inner.shared.load(Ordering::Relaxed)) {
+            let biased = &mut *inner.biased.get();
+            inner
+                .shared
+                .fetch_add(MERGED + *biased as isize, Ordering::AcqRel);
+            *biased = 0;
+        }
+        // Give back the reference the queue entry was holding.
+        Self::release_shared(ptr);
+    }
+
+    /// Drop path for a thread other than the owner while still unmerged.
+    /// Returns `false` if the allocation turned out to be merged already.
+    fn drop_unmerged_remote(&self) -> bool {
+        let inner = self.inner();
+        let mut current = inner.shared.load(Ordering::Relaxed);
+        loop {
+            if is_merged(current) {
+                return false;
+            }
+            if current <= 0 && !inner.queued.load(Ordering::Relaxed) {
+                // Decrementing would leave the total only visible to the owner,
+                // so hand this reference to the owner's queue instead.
+                if inner
+                    .queued
+                    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    MERGE_QUEUE.lock().unwrap().push(QueuedMerge {
+                        owner: inner.owner,
+                        ptr: self.ptr.as_ptr() as *const (),
+                        merge: Self::merge_queued_entry,
+                    });
+                    return true;
+                }
+                continue;
+            }
+            match inner.shared.compare_exchange_weak(
+                current,
+                current - 1,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl<T> Clone for BiasedArc<T> {
+    fn clone(&self) -> Self {
+        let inner = self.inner();
+        if self.on_biased_path() {
+            unsafe { *inner.biased.get() += 1 };
+        } else {
+            inner.shared.fetch_add(1, Ordering::Relaxed);
+        }
+        BiasedArc { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for BiasedArc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner().value
+    }
+}
+
+impl<T> Drop for BiasedArc<T> {
+    fn drop(&mut self) {
+        let inner = self.inner();
+        if self.on_biased_path() {
+            let biased = unsafe { &mut *inner.biased.get() };
+            *biased -= 1;
+            if *biased == 0 {
+                // Hand over to the shared count; whatever is left there is the
+                // whole live count now.
+                if inner.shared.fetch_add(MERGED, Ordering::AcqRel) == 0 {
+                    unsafe {
+                        let _ = Box::from_raw(self.ptr.as_ptr());
+                    }
+                }
+            }
+            return;
+        }
+        if inner.owner != current_thread_id() && self.drop_unmerged_remote() {
+            return;
+        }
+        unsafe { Self::release_shared(self.ptr) };
+    }
+}
+
+impl<T> Constructor<T> for BiasedArc<T> {
+    fn new(value: T) -> Self {
+        BiasedArc::new(value)
+    }
+}
+
+impl CountedRc<Entity> for BiasedArc<Entity> {
+    /// A reference handed to the merge queue counts as live until its owner
+    /// merges it.
+    ///
+    /// # Panics
+    /// Before the counts are merged, only the owner thread can see the biased
+    /// one, so any other thread panics.
+    fn strong_count(&self) -> usize {
+        let inner = self.inner();
+        let shared = inner.shared.load(Ordering::Acquire);
+        if is_merged(shared) {
+            return (shared - MERGED) as usize;
+        }
+        assert!(
+            inner.owner == current_thread_id(),
+            "only the owner thread can count an unmerged BiasedArc"
+        );
+        (unsafe { *inner.biased.get() } as isize + shared) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Counts how many times it has been dropped.
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The shared count, once merged.
+    fn merged_count<T>(arc: &BiasedArc<T>) -> Option<isize> {
+        let shared = arc.inner().shared.load(Ordering::Acquire);
+        is_merged(shared).then_some(shared - MERGED)
+    }
+
+    #[test]
+    fn owner_handles_dropped_elsewhere_go_through_the_merge_queue() {
+        let drops = AtomicUsize::new(0);
+        let owned = BiasedArc::new(DropCounter(&drops));
+        let cloned = owned.clone();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                drop(owned);
+                drop(cloned);
+            });
+        });
+        // Both references are gone, but only the owner can tell.
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        let queued = MERGE_QUEUE
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.owner == current_thread_id())
+            .count();
+        assert_eq!(queued, 1);
+        BiasedArc::<DropCounter>::merge_queued();
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn the_owner_merges_when_its_biased_count_reaches_zero() {
+        let drops = AtomicUsize::new(0);
+        let owned = BiasedArc::new(DropCounter(&drops));
+        let remote = thread::scope(|scope| {
+            let owned = &owned;
+            scope
+                .spawn(move || (0..3).map(|_| owned.clone()).collect::<Vec<_>>())
+                .join()
+                .unwrap()
+        });
+        assert_eq!(merged_count(&owned), None);
+        drop(owned);
+        assert_eq!(merged_count(&remote[0]), Some(3));
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                assert_eq!(merged_count(&remote[0]), Some(3));
+                drop(remote);
+            });
+        });
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn clones_crossing_threads_balance_out() {
+        let owned = BiasedArc::new(Entity {
+            id: 0,
+            x: 0.0,
+            y: 0.0,
+        });
+        let mut local = Vec::new();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let sent = owned.clone();
+                local.push(owned.clone());
+                scope.spawn(move || {
+                    let kept: Vec<_> = (0..100).map(|_| sent.clone()).collect();
+                    drop(sent);
+                    kept.len()
+                });
+            }
+        });
+        // Whichever remote thread took the shared count to zero queued its
+        // last reference rather than go negative.
+        assert_eq!(owned.strong_count(), 6);
+        BiasedArc::<Entity>::merge_queued();
+        assert_eq!(merged_count(&owned), Some(5));
+        assert_eq!(owned.strong_count(), 5);
+        drop(local);
+        assert_eq!(owned.strong_count(), 1);
+    }
+}
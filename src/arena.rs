@@ -0,0 +1,492 @@
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use crate::entity::Entity;
+use crate::rc::{Constructor, CountedRc};
+
+// ========================
+// Define the Entity Arena
+// ========================
+
+/// One arena slot: a count and room for an entity. Slots never move, so
+/// handles may point at them directly.
+pub struct Slot {
+    count: Cell<usize>,
+    value: UnsafeCell<MaybeUninit<Entity>>,
+}
+
+struct EntityArena {
+    // The boxed slot slice, kept as a raw pointer so that touching the rest
+    // of the arena never reborrows slots that handles point into.
+    slots: *mut Slot,
+    capacity: usize,
+    /// Slots freed by a drop, reused before `next`.
+    free: Vec<u32>,
+    /// First slot that has never been handed out.
+    next: u32,
+    /// Slots currently holding an entity.
+    live: usize,
+    /// How many times each slot has been released, so an `ArenaId` of a
+    /// slot's previous occupant doesn't match its current one. Kept apart
+    /// from the slots to leave their layout, and the benchmark, unchanged.
+    generations: Vec<u32>,
+}
+
+thread_local! {
+    // The arena installed by `with_entity_arena`, and its first slot, which
+    // index handles add their index to on every dereference.
+    static ARENA: Cell<*mut EntityArena> = const { Cell::new(ptr::null_mut()) };
+    static SLOTS: Cell<*const Slot> = const { Cell::new(ptr::null()) };
+    // The arena's generations, which generation-checked handles compare
+    // against on every dereference.
+    static GENERATIONS: Cell<*const u32> = const { Cell::new(ptr::null()) };
+}
+
+/// Runs `f` with a fresh thread-local arena of `capacity` entity slots, which
+/// every `ArenaRc` created inside `f` allocates from.
+///
+/// Panics if an arena is already installed, or if handles are still alive
+/// when `f` returns (the arena is then leaked, and left installed, so they
+/// stay valid). If `f` panics, the arena is uninstalled as it unwinds.
+pub fn with_entity_arena<R>(capacity: usize, f: impl FnOnce() -> R) -> R {
+    assert!(
+        ARENA.with(Cell::get).is_null(),
+        "an entity arena is already installed on this thread"
+    );
+    assert!(
+        capacity <= u32::MAX as usize,
+        "arena capacity must fit in u32"
+    );
+    let slots = (0..capacity)
+        .map(|_| Slot {
+            count: Cell::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect::<Box<[Slot]>>();
+    let slots = Box::into_raw(slots) as *mut Slot;
+    let arena = Box::into_raw(Box::new(EntityArena {
+        slots,
+        capacity,
+        free: Vec::new(),
+        next: 0,
+        live: 0,
+        generations: vec![0; capacity],
+    }));
+    ARENA.with(|current| current.set(arena));
+    SLOTS.with(|current| current.set(slots));
+    // The vector never grows, so its buffer stays put.
+    GENERATIONS.with(|current| current.set(unsafe { (*arena).generations.as_ptr() }));
+    let installed = InstalledArena(arena);
+
+    let result = f();
+
+    let live = unsafe { (*arena).live };
+    drop(installed);
+    assert!(live == 0, "{} arena handles outlived the arena", live);
+    result
+}
+
+/// Uninstalls and frees the arena when `with_entity_arena` returns or
+/// unwinds, unless handles to it are still alive.
+struct InstalledArena(*mut EntityArena);
+
+impl Drop for InstalledArena {
+    fn drop(&mut self) {
+        let arena = self.0;
+        // Handles left in `f`'s frame have already dropped while unwinding,
+        // so any still alive escaped it and need the arena where it is.
+        if unsafe { (*arena).live } != 0 {
+            return;
+        }
+        ARENA.with(|current| current.set(ptr::null_mut()));
+        SLOTS.with(|current| current.set(ptr::null()));
+        GENERATIONS.with(|current| current.set(ptr::null()));
+        unsafe {
+            let arena = Box::from_raw(arena);
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                arena.slots,
+                arena.capacity,
+            )));
+        }
+    }
+}
+
+/// Moves `value` into a free slot of the installed arena.
+fn allocate(value: Entity) -> (NonNull<Slot>, u32) {
+    let arena = ARENA.with(Cell::get);
+    assert!(!arena.is_null(), "no entity arena installed on this thread");
+    let arena = unsafe { &mut *arena };
+    let index = match arena.free.pop() {
+        Some(index) => index,
+        None => {
+            assert!(
+                (arena.next as usize) < arena.capacity,
+                "entity arena capacity exceeded"
+            );
+            arena.next += 1;
+            arena.next - 1
+        }
+    };
+    let slot = unsafe { &*arena.slots.add(index as usize) };
+    unsafe { (*slot.value.get()).write(value) };
+    slot.count.set(1);
+    arena.live += 1;
+    (NonNull::from(slot), index)
+}
+
+/// Drops the entity in `slot` and puts the slot back on the free list.
+///
+/// # Safety
+/// `slot` must be slot `index` of the installed arena, holding an entity
+/// whose count just reached zero.
+unsafe fn release(slot: NonNull<Slot>, index: u32) {
+    (*slot.as_ref().value.get()).assume_init_drop();
+    let arena = &mut *ARENA.with(Cell::get);
+    let generation = &mut arena.generations[index as usize];
+    *generation = generation.wrapping_add(1);
+    arena.free.push(index);
+    arena.live -= 1;
+}
+
+// ========================
+// Select a Handle Representation
+// ========================
+
+/// How an `ArenaRc` remembers its slot.
+pub trait HandleRepr: Copy {
+    /// Builds the handle for slot `index`, located at `slot`.
+    fn new(slot: NonNull<Slot>, index: u32) -> Self;
+    fn slot(self) -> NonNull<Slot>;
+    fn index(self) -> u32;
+
+    /// Runs on every dereference. Nothing to do unless the representation
+    /// can tell a stale handle from a live one.
+    #[inline]
+    fn check(self) {}
+}
+
+/// Stores the slot's address: dereferencing is a single indirection.
+#[derive(Clone, Copy)]
+pub struct PtrRepr(NonNull<Slot>);
+
+impl HandleRepr for PtrRepr {
+    #[inline]
+    fn new(slot: NonNull<Slot>, _index: u32) -> Self {
+        PtrRepr(slot)
+    }
+
+    #[inline]
+    fn slot(self) -> NonNull<Slot> {
+        self.0
+    }
+
+    fn index(self) -> u32 {
+        let base = SLOTS.with(Cell::get);
+        unsafe { self.0.as_ptr().offset_from(base) as u32 }
+    }
+}
+
+/// Stores the slot's index: half the size of a pointer, and compaction-
+/// friendly, but every dereference first looks up the arena's base.
+#[derive(Clone, Copy)]
+pub struct IndexRepr(u32);
+
+impl HandleRepr for IndexRepr {
+    #[inline]
+    fn new(_slot: NonNull<Slot>, index: u32) -> Self {
+        IndexRepr(index)
+    }
+
+    #[inline]
+    fn slot(self) -> NonNull<Slot> {
+        let base = SLOTS.with(Cell::get);
+        unsafe { NonNull::new_unchecked(base.add(self.0 as usize) as *mut Slot) }
+    }
+
+    fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Stores the slot's index and the generation it was created in, and checks
+/// on every dereference that the slot hasn't been recycled since, panicking
+/// if it has.
+///
+/// An `ArenaRc`'s own count already keeps its slot from being recycled, so
+/// for it the check can't fail and only shows what it costs; it's
+/// `ArenaRc::upgrade` from an `ArenaId` where stale handles get turned away.
+///
+/// Its generations live in the same thread-local arena, so like every
+/// `ArenaRc` its handles can't leave the thread:
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<rc_playground::ArenaRc<rc_playground::GenRepr>>();
+/// ```
+#[derive(Clone, Copy)]
+pub struct GenRepr {
+    index: u32,
+    generation: u32,
+}
+
+impl HandleRepr for GenRepr {
+    #[inline]
+    fn new(_slot: NonNull<Slot>, index: u32) -> Self {
+        GenRepr {
+            index,
+            generation: current_generation(index),
+        }
+    }
+
+    #[inline]
+    fn slot(self) -> NonNull<Slot> {
+        IndexRepr(self.index).slot()
+    }
+
+    fn index(self) -> u32 {
+        self.index
+    }
+
+    #[inline]
+    fn check(self) {
+        assert!(
+            current_generation(self.index) == self.generation,
+            "stale arena handle: slot {} was recycled",
+            self.index
+        );
+    }
+}
+
+#[inline]
+fn current_generation(index: u32) -> u32 {
+    let generations = GENERATIONS.with(Cell::get);
+    unsafe { *generations.add(index as usize) }
+}
+
+// ========================
+// Implement ArenaRc
+// ========================
+
+/// Identifies an entity in the arena for as long as the arena lives.
+///
+/// Freed slots are reused, so a slot index (or address) alone names
+/// whichever entity occupies the slot now; the generation tells a stale id
+/// from the current occupant's. It wraps after `u32::MAX` reuses of one slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArenaId {
+    pub index: u32,
+    pub generation: u32,
+}
+
+/// A reference-counted entity living in the thread's entity arena (see
+/// `with_entity_arena`), with the handle representation chosen by `R`.
+/// Like `CustomRc` it is single-threaded only: the arena it lives in belongs
+/// to one thread, so a handle is neither `Send` nor `Sync`, even one that
+/// only stores an index.
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<rc_playground::ArenaRc<rc_playground::IndexRepr>>();
+/// ```
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<rc_playground::ArenaRc<rc_playground::IndexRepr>>();
+/// ```
+pub struct ArenaRc<R: HandleRepr> {
+    repr: R,
+    _thread: PhantomData<*const Slot>,
+}
+
+impl<R: HandleRepr> ArenaRc<R> {
+    /// Creates a new entity in the installed arena.
+    pub fn new(value: Entity) -> Self {
+        let (slot, index) = allocate(value);
+        ArenaRc {
+            repr: R::new(slot, index),
+            _thread: PhantomData,
+        }
+    }
+
+    /// This entity's `ArenaId`.
+    pub fn id(this: &Self) -> ArenaId {
+        let index = this.repr.index();
+        let arena = unsafe { &*ARENA.with(Cell::get) };
+        ArenaId {
+            index,
+            generation: arena.generations[index as usize],
+        }
+    }
+
+    /// A new handle to the entity `id` names, unless it has been dropped
+    /// (and its slot possibly reused) since, or `id` is from another arena
+    /// and out of range. No arena installed means no entity either.
+    pub fn upgrade(id: ArenaId) -> Option<Self> {
+        let arena = ARENA.with(Cell::get);
+        if arena.is_null() {
+            return None;
+        }
+        let arena = unsafe { &*arena };
+        let index = id.index as usize;
+        if index >= arena.capacity || arena.generations[index] != id.generation {
+            return None;
+        }
+        let slot = unsafe { &*arena.slots.add(index) };
+        // A slot that was never handed out has a matching generation zero.
+        if slot.count.get() == 0 {
+            return None;
+        }
+        slot.count.set(slot.count.get() + 1);
+        Some(ArenaRc {
+            repr: R::new(NonNull::from(slot), id.index),
+            _thread: PhantomData,
+        })
+    }
+
+    /// Whether both handles share one entity.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.repr.slot() == other.repr.slot()
+    }
+
+    fn slot(&self) -> &Slot {
+        unsafe { self.repr.slot().as_ref() }
+    }
+}
+
+impl<R: HandleRepr> Clone for ArenaRc<R> {
+    fn clone(&self) -> Self {
+        let count = &self.slot().count;
+        count.set(count.get() + 1);
+        ArenaRc {
+            repr: self.repr,
+            _thread: PhantomData,
+        }
+    }
+}
+
+impl<R: HandleRepr> Deref for ArenaRc<R> {
+    type Target = Entity;
+    fn deref(&self) -> &Self::Target {
+        self.repr.check();
+        unsafe { (*self.slot().value.get()).assume_init_ref() }
+    }
+}
+
+impl<R: HandleRepr> Drop for ArenaRc<R> {
+    fn drop(&mut self) {
+        let count = &self.slot().count;
+        count.set(count.get() - 1);
+        if count.get() == 0 {
+            unsafe { release(self.repr.slot(), self.repr.index()) };
+        }
+    }
+}
+
+impl<R: HandleRepr> Constructor<Entity> for ArenaRc<R> {
+    fn new(value: Entity) -> Self {
+        ArenaRc::new(value)
+    }
+}
+
+impl<R: HandleRepr> CountedRc<Entity> for ArenaRc<R> {
+    fn strong_count(&self) -> usize {
+        self.slot().count.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn entity(id: usize, x: f32, y: f32) -> Entity {
+        Entity { id, x, y }
+    }
+
+    #[test]
+    fn index_handles_are_half_the_size_of_pointer_handles() {
+        assert_eq!(size_of::<ArenaRc<PtrRepr>>(), size_of::<*const Slot>());
+        assert_eq!(size_of::<ArenaRc<IndexRepr>>(), size_of::<u32>());
+    }
+
+    #[test]
+    fn representations_share_one_slot_per_entity() {
+        with_entity_arena(4, || {
+            let by_ptr = ArenaRc::<PtrRepr>::new(entity(0, 1.0, 2.0));
+            let by_index = ArenaRc::<IndexRepr>::new(entity(1, 3.0, 4.0));
+            let shared = by_index.clone();
+            assert_eq!(by_ptr.id, 0);
+            assert!(ArenaRc::ptr_eq(&by_index, &shared));
+            assert_eq!(shared.strong_count(), 2);
+            assert_eq!(ArenaRc::id(&by_index).index, 1);
+        });
+    }
+
+    #[test]
+    fn a_panic_inside_the_arena_uninstalls_it() {
+        let unwound = panic::catch_unwind(AssertUnwindSafe(|| {
+            with_entity_arena(2, || {
+                let _entity = ArenaRc::<IndexRepr>::new(entity(0, 0.0, 0.0));
+                panic!("setup failed");
+            })
+        }));
+        assert!(unwound.is_err());
+        assert!(ARENA.with(Cell::get).is_null());
+        // A fresh arena can be installed again.
+        with_entity_arena(1, || drop(ArenaRc::<IndexRepr>::new(entity(0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn upgrade_rejects_an_id_whose_slot_was_recycled() {
+        with_entity_arena(1, || {
+            let first = ArenaRc::<GenRepr>::new(entity(0, 0.0, 0.0));
+            let stale = ArenaRc::id(&first);
+            assert!(ArenaRc::<GenRepr>::upgrade(stale).is_some());
+            drop(first);
+            let second = ArenaRc::<GenRepr>::new(entity(1, 0.0, 0.0));
+            let fresh = ArenaRc::id(&second);
+            assert_eq!(stale.index, fresh.index);
+            assert!(ArenaRc::<GenRepr>::upgrade(stale).is_none());
+            assert_eq!(ArenaRc::<GenRepr>::upgrade(fresh).unwrap().id, 1);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "stale arena handle")]
+    fn a_stale_generation_panics_instead_of_reading_the_slot() {
+        with_entity_arena(1, || {
+            let first = ArenaRc::<GenRepr>::new(entity(0, 0.0, 0.0));
+            // A copy of the handle that holds no count of its own, as if its
+            // count had been lost, so the slot can be recycled under it.
+            let stale = std::mem::ManuallyDrop::new(ArenaRc::<GenRepr> {
+                repr: first.repr,
+                _thread: PhantomData,
+            });
+            drop(first);
+            let _second = ArenaRc::<GenRepr>::new(entity(1, 0.0, 0.0));
+            let _ = stale.id;
+        });
+    }
+
+    #[test]
+    fn a_recycled_slot_gets_a_new_id() {
+        with_entity_arena(1, || {
+            let mut ids = Vec::new();
+            for id in 0..3 {
+                let entity = ArenaRc::<IndexRepr>::new(entity(id, 0.0, 0.0));
+                ids.push(ArenaRc::id(&entity));
+            }
+            assert!(ids.iter().all(|id| id.index == ids[0].index));
+            assert_eq!(
+                ids.iter()
+                    .map(|id| id.generation - ids[0].generation)
+                    .collect::<Vec<_>>(),
+                [0, 1, 2]
+            );
+        });
+    }
+}
@@ -0,0 +1,1550 @@
+use std::cell::{Cell, RefCell};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::allocator::{BenchAllocator, SizeHistogram};
+use crate::arc::{BiasedArc, CustomArc};
+use crate::arena::{with_entity_arena, ArenaRc, GenRepr, IndexRepr, PtrRepr};
+use crate::cell::{CellEntity, RefCellEntity, SharedEntity};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{total_operations, Config, Implementation};
+use crate::copy::CopyRc;
+use crate::entity::{Entity, NamedEntity};
+use crate::error::BenchError;
+use crate::game::{
+    CloneDropSplit, Game, GameBuilder, ManualGame, MutOrRead, SoaGame, UpdateGame, HOT_SET_SHARE,
+};
+use crate::named::Named;
+use crate::pool::PooledRc;
+use crate::rc::{CountedRc, CustomRc, MutableRc, StdArcWrapper, StdRcWrapper, WeakRc};
+use crate::report::{
+    median, ns_per_operation, sample_until_stable, Matrix, MatrixRow, StableSamples,
+};
+use crate::rss::{peak_rss, reset_peak_rss};
+use crate::small::SmallRc;
+
+// ========================
+// Benchmarking Function
+// ========================
+
+/// Bytes streamed through to evict the entities before a cold run; larger
+/// than any last-level cache the benchmark is likely to meet.
+const CACHE_FLUSH_BYTES: usize = 64 << 20;
+
+/// Whether a benchmarked game ran all of its frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Completed,
+    /// The timeout ran out after this many frames; `run` covers only those.
+    TimedOut {
+        frames_run: usize,
+    },
+}
+
+/// The timings of one benchmarked game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    pub name: String,
+    pub status: RunStatus,
+    /// `size_of` the handle type.
+    pub handle_size: usize,
+    /// Building the game, including `setup`.
+    pub setup: Duration,
+    /// `Game::run` alone.
+    pub run: Duration,
+    /// Logical operations the run did: frames run x operations per frame x
+    /// entities.
+    pub operations: usize,
+    /// `total_operations` reads chasing from entity to entity, after the
+    /// run, when `config.dependent_access` asks for them.
+    pub dependent: Option<Duration>,
+    /// With `config.zipf`, how many entities took `HOT_SET_SHARE` of the
+    /// reads.
+    pub hot_set: Option<usize>,
+    /// Handles left alive after the run, once the balance check has confirmed
+    /// that each is accounted for. `None` for games without refcounts.
+    pub live_handles: Option<usize>,
+    /// Dropping the game afterwards, and with it every handle ("drop all").
+    pub teardown: Duration,
+    /// The untimed game `Bencher::run` played right before, if it warmed
+    /// up.
+    pub warm_up: Option<Duration>,
+    /// How many bytes building and running the game raised the process's
+    /// peak RSS by, over its RSS beforehand (where the peak can be reset,
+    /// otherwise over the peak so far). `None` without the `rusage` feature.
+    pub peak_rss_growth: Option<usize>,
+    /// Every allocation made while building and running the game, if the
+    /// `Bencher` was given the global allocator to track them with.
+    pub allocation_sizes: Option<SizeHistogram>,
+}
+
+impl BenchResult {
+    /// `run` in nanoseconds per logical operation, comparable across
+    /// configurations that do different amounts of work.
+    pub fn run_per_operation(&self) -> f64 {
+        ns_per_operation(self.run, self.operations)
+    }
+
+    /// Setup plus run; `teardown` is reported on its own.
+    pub fn total(&self) -> Duration {
+        self.setup + self.run
+    }
+}
+
+/// Builds and runs one game of `RcType`. With `config.flush_cache` the
+/// caches are flushed between setup and the timed run, otherwise every
+/// entity is read once so the run starts warm.
+pub fn benchmark<RcType>(name: &str, config: &Config) -> Result<BenchResult, BenchError>
+where
+    RcType: CountedRc<Entity>,
+{
+    time_rc::<RcType>(name, config, &SystemClock)
+}
+
+/// `benchmark` for the struct-of-arrays baseline, which has no handles.
+pub fn benchmark_soa(name: &str, config: &Config) -> Result<BenchResult, BenchError> {
+    time_soa(name, config, &SystemClock)
+}
+
+/// `benchmark` for a game whose operations update the entity in place.
+pub fn benchmark_update<E: SharedEntity>(
+    name: &str,
+    config: &Config,
+) -> Result<BenchResult, BenchError> {
+    time_update::<E>(name, config, &SystemClock)
+}
+
+fn time_rc<RcType: CountedRc<Entity>>(
+    name: &str,
+    config: &Config,
+    clock: &dyn Clock,
+) -> Result<BenchResult, BenchError> {
+    time_game(name, std::mem::size_of::<RcType>(), config, clock, || {
+        GameBuilder::from_config(config)
+            .name(name)
+            .build::<RcType>()
+    })
+}
+
+/// `benchmark`, with the entities spawned through a boxed `Factory`.
+pub fn benchmark_factory<RcType>(name: &str, config: &Config) -> Result<BenchResult, BenchError>
+where
+    RcType: CountedRc<Entity> + 'static,
+{
+    time_factory::<RcType>(name, config, &SystemClock)
+}
+
+fn time_factory<RcType: CountedRc<Entity> + 'static>(
+    name: &str,
+    config: &Config,
+    clock: &dyn Clock,
+) -> Result<BenchResult, BenchError> {
+    let factory = GameBuilder::from_config(config).factory::<RcType>();
+    time_game(name, std::mem::size_of::<RcType>(), config, clock, || {
+        GameBuilder::from_config(config)
+            .name(name)
+            .build_with_factory(&factory)
+    })
+}
+
+fn time_soa(name: &str, config: &Config, clock: &dyn Clock) -> Result<BenchResult, BenchError> {
+    time_game(name, 0, config, clock, || {
+        GameBuilder::from_config(config).name(name).build_soa()
+    })
+}
+
+fn time_manual(name: &str, config: &Config, clock: &dyn Clock) -> Result<BenchResult, BenchError> {
+    time_game(name, 0, config, clock, || {
+        GameBuilder::from_config(config).name(name).build_manual()
+    })
+}
+
+fn time_update<E: SharedEntity>(
+    name: &str,
+    config: &Config,
+    clock: &dyn Clock,
+) -> Result<BenchResult, BenchError> {
+    time_game(name, std::mem::size_of::<E>(), config, clock, || {
+        GameBuilder::from_config(config)
+            .name(name)
+            .build_update::<E>()
+    })
+}
+
+/// The parts of the games that `time_game` uses.
+trait Playable {
+    fn checksum(&self) -> f64;
+    fn run_with_clock(&mut self, clock: &dyn Clock) -> usize;
+    fn chase(&self, steps: usize) -> usize;
+
+    fn hot_set(&self) -> Option<usize>;
+
+    /// `Game::check_balance`, where there are counts to check.
+    fn check_balance(&self) -> Result<Option<usize>, BenchError> {
+        Ok(None)
+    }
+}
+
+impl<RcType: CountedRc<Entity>> Playable for Game<RcType> {
+    fn checksum(&self) -> f64 {
+        Game::checksum(self)
+    }
+
+    fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        Game::run_with_clock(self, clock)
+    }
+
+    fn chase(&self, steps: usize) -> usize {
+        Game::chase(self, steps)
+    }
+
+    fn hot_set(&self) -> Option<usize> {
+        Game::hot_set(self)
+    }
+
+    fn check_balance(&self) -> Result<Option<usize>, BenchError> {
+        Game::check_balance(self).map(Some)
+    }
+}
+
+impl Playable for ManualGame {
+    fn checksum(&self) -> f64 {
+        ManualGame::checksum(self)
+    }
+
+    fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        ManualGame::run_with_clock(self, clock)
+    }
+
+    fn chase(&self, steps: usize) -> usize {
+        ManualGame::chase(self, steps)
+    }
+
+    fn hot_set(&self) -> Option<usize> {
+        ManualGame::hot_set(self)
+    }
+
+    fn check_balance(&self) -> Result<Option<usize>, BenchError> {
+        ManualGame::check_balance(self).map(Some)
+    }
+}
+
+impl<E: SharedEntity> Playable for UpdateGame<E> {
+    fn checksum(&self) -> f64 {
+        UpdateGame::checksum(self)
+    }
+
+    fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        UpdateGame::run_with_clock(self, clock)
+    }
+
+    fn chase(&self, steps: usize) -> usize {
+        UpdateGame::chase(self, steps)
+    }
+
+    fn hot_set(&self) -> Option<usize> {
+        UpdateGame::hot_set(self)
+    }
+}
+
+impl Playable for SoaGame {
+    fn checksum(&self) -> f64 {
+        SoaGame::checksum(self)
+    }
+
+    fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        SoaGame::run_with_clock(self, clock)
+    }
+
+    fn chase(&self, steps: usize) -> usize {
+        SoaGame::chase(self, steps)
+    }
+
+    fn hot_set(&self) -> Option<usize> {
+        SoaGame::hot_set(self)
+    }
+}
+
+fn time_game<G: Playable>(
+    name: &str,
+    handle_size: usize,
+    config: &Config,
+    clock: &dyn Clock,
+    build: impl FnOnce() -> Result<G, BenchError>,
+) -> Result<BenchResult, BenchError> {
+    // Without a reset, everything after the first (or warm-up) game would
+    // see the peak that game left behind.
+    reset_peak_rss();
+    prewarm_allocator(config.prewarm_blocks);
+    let peak_before = peak_rss();
+    let start = clock.now();
+    let mut game = build()?;
+    let setup = clock.since(start);
+    if config.flush_cache {
+        flush_cache();
+    } else {
+        black_box(game.checksum());
+    }
+    let start = clock.now();
+    let frames_run = game.run_with_clock(clock);
+    let run = clock.since(start);
+    let dependent = if config.dependent_access {
+        let steps = config.total_operations()?;
+        let start = clock.now();
+        black_box(game.chase(steps));
+        Some(clock.since(start))
+    } else {
+        None
+    };
+    let live_handles = game.check_balance()?;
+    let hot_set = game.hot_set();
+    let peak_rss_growth = peak_before
+        .zip(peak_rss())
+        .map(|(before, after)| after.saturating_sub(before));
+    // Hide the game from the optimizer, so its drop can't be folded into (or
+    // hoisted out of) anything around the timer.
+    let game = black_box(game);
+    let start = clock.now();
+    drop(game);
+    let teardown = clock.since(start);
+    let operations =
+        total_operations(frames_run, config.operations_per_frame, config.num_entities)?;
+    let status = if frames_run < config.num_frames {
+        RunStatus::TimedOut { frames_run }
+    } else {
+        RunStatus::Completed
+    };
+    Ok(BenchResult {
+        name: name.to_string(),
+        status,
+        handle_size,
+        setup,
+        run,
+        operations,
+        dependent,
+        hot_set,
+        live_handles,
+        teardown,
+        warm_up: None,
+        peak_rss_growth,
+        allocation_sizes: None,
+    })
+}
+
+/// What one entity of a `StdRc` or `CustomRc` game allocates: the two counts
+/// and the entity.
+type EntityBlock = (usize, usize, Entity);
+
+/// Allocates `blocks` entity-sized blocks and frees them again, so the
+/// allocator has grown its arenas and filled its free lists for that size
+/// before a setup is timed, the way every setup after the first finds them.
+fn prewarm_allocator(blocks: usize) {
+    let warm: Vec<Box<EntityBlock>> = (0..blocks)
+        .map(|id| Box::new((0, 0, Entity { id, x: 0.0, y: 0.0 })))
+        .collect();
+    drop(black_box(warm));
+}
+
+/// Streams `CACHE_FLUSH_BYTES` through the caches so nothing touched before
+/// is still resident.
+fn flush_cache() {
+    let mut buffer = vec![0u8; CACHE_FLUSH_BYTES];
+    for (index, byte) in buffer.iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+    black_box(buffer.iter().map(|&byte| byte as u64).sum::<u64>());
+}
+
+/// Runs `A` and `B` over the same seeded game (one frame, one operation) and
+/// fails unless both end with exactly the same coordinate checksum and strong
+/// counts, so a broken implementation can't get away with just reporting a
+/// time.
+pub fn cross_check<A, B>(config: &Config) -> Result<(), BenchError>
+where
+    A: CountedRc<Entity>,
+    B: CountedRc<Entity>,
+{
+    let builder = GameBuilder::from_config(config)
+        .name("cross-check")
+        .quiet(true)
+        .frames(1)
+        .operations_per_frame(1)
+        .seed(config.seed);
+    let mut a = builder.clone().build::<A>()?;
+    let mut b = builder.build::<B>()?;
+    a.run();
+    b.run();
+    if a.checksum() != b.checksum() {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "{} ended with checksum {}, {} with {}",
+            std::any::type_name::<A>(),
+            a.checksum(),
+            std::any::type_name::<B>(),
+            b.checksum()
+        )));
+    }
+    if a.strong_counts() != b.strong_counts() {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "{} and {} disagree on strong counts",
+            std::any::type_name::<A>(),
+            std::any::type_name::<B>()
+        )));
+    }
+    Ok(())
+}
+
+/// Clones made (and dropped) by `check_clone_allocation_free`.
+const ALLOCATION_CHECK_CLONES: usize = 1_000;
+
+/// Fails unless cloning a `CustomRc` leaves `allocator` untouched: a clone
+/// must only bump the count. `make_mut` on a shared handle, which does
+/// allocate, is tracked the same way first, to show the tracking works.
+pub fn check_clone_allocation_free(allocator: &BenchAllocator) -> Result<(), BenchError> {
+    let mut rc = CustomRc::new(Entity {
+        id: 0,
+        x: 0.0,
+        y: 0.0,
+    });
+
+    let shared = rc.clone();
+    allocator.begin_size_tracking();
+    CustomRc::make_mut(&mut rc).x += 1.0;
+    let copied = allocator.end_size_tracking().total();
+    drop(shared);
+    if copied == 0 {
+        return Err(BenchError::CrossCheckFailed(
+            "the allocator saw no allocation from CustomRc::make_mut".to_string(),
+        ));
+    }
+
+    allocator.begin_size_tracking();
+    for _ in 0..ALLOCATION_CHECK_CLONES {
+        drop(black_box(rc.clone()));
+    }
+    let cloned = allocator.end_size_tracking().total();
+    if cloned != 0 {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "{} CustomRc clones made {} allocations",
+            ALLOCATION_CHECK_CLONES, cloned
+        )));
+    }
+    Ok(())
+}
+
+/// Times `Game::run_mut_or_read` for `StdRc` (over `Rc::get_mut`) and
+/// `CustomRc` with `shared_fraction` of the entities shared, and prints how
+/// often each took the mutating fast path. Each is warmed up with an untimed
+/// game of its own first.
+pub fn run_mut_or_read_benchmark(config: &Config, shared_fraction: f64) -> Result<(), BenchError> {
+    println!(
+        "get_mut or read, {:.0}% of entities shared...",
+        shared_fraction * 100.0
+    );
+    let operations = config.total_operations()?;
+    for (name, (duration, outcome)) in [
+        (
+            "StdRc",
+            time_mut_or_read::<StdRcWrapper<Entity>>(config, shared_fraction)?,
+        ),
+        (
+            "CustomRc",
+            time_mut_or_read::<CustomRc<Entity>>(config, shared_fraction)?,
+        ),
+    ] {
+        println!(
+            "{:<10} {:?} ({:.2} ns/op), fast path {:.1}% ({} mutated, {} read)",
+            name,
+            duration,
+            ns_per_operation(duration, operations),
+            outcome.fast_path_share() * 100.0,
+            outcome.mutated,
+            outcome.read
+        );
+    }
+    println!();
+    Ok(())
+}
+
+fn time_mut_or_read<RcType: MutableRc<Entity>>(
+    config: &Config,
+    shared_fraction: f64,
+) -> Result<(Duration, MutOrRead), BenchError> {
+    let build = || {
+        GameBuilder::from_config(config)
+            .quiet(true)
+            .build::<RcType>()
+    };
+    black_box(build()?.run_mut_or_read(shared_fraction));
+    let mut game = build()?;
+    let start = Instant::now();
+    let outcome = game.run_mut_or_read(shared_fraction);
+    Ok((start.elapsed(), outcome))
+}
+
+/// Times `Game::run`, which clones every handle it reads through, against
+/// `Game::run_no_clone`, which only derefs it, for `StdRc` and `CustomRc`,
+/// and prints the difference per operation: the clone tax. Each is warmed
+/// up with an untimed game of its own first.
+pub fn run_clone_tax_benchmark(config: &Config) -> Result<(), BenchError> {
+    println!("Reading with and without a clone (ns/op)...");
+    let operations = config.total_operations()?;
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "", "clone", "deref only", "clone tax"
+    );
+    for (name, (cloned, borrowed)) in [
+        ("StdRc", time_clone_tax::<StdRcWrapper<Entity>>(config)?),
+        ("CustomRc", time_clone_tax::<CustomRc<Entity>>(config)?),
+    ] {
+        let (cloned, borrowed) = (
+            ns_per_operation(cloned, operations),
+            ns_per_operation(borrowed, operations),
+        );
+        println!(
+            "{:<10} {:>12.3} {:>12.3} {:>12.3}",
+            name,
+            cloned,
+            borrowed,
+            cloned - borrowed
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// `(run, run_no_clone)` of two fresh games, with everything that would
+/// make `run` do more than `run_no_clone` (churn, a timeout, Zipf reads,
+/// heavy compute) turned off.
+fn time_clone_tax<RcType: CountedRc<Entity>>(
+    config: &Config,
+) -> Result<(Duration, Duration), BenchError> {
+    let builder = GameBuilder::from_config(&Config {
+        timeout: None,
+        churn_per_frame: 0,
+        zipf: None,
+        heavy_compute: false,
+        ..config.clone()
+    })
+    .quiet(true);
+    black_box(builder.clone().build::<RcType>()?.run());
+    let mut game = builder.clone().build::<RcType>()?;
+    let start = Instant::now();
+    game.run();
+    let cloned = start.elapsed();
+    let mut game = builder.build::<RcType>()?;
+    let start = Instant::now();
+    game.run_no_clone();
+    Ok((cloned, start.elapsed()))
+}
+
+/// Times `Game::run` against `WeakGame::run` for `StdRc` and `CustomRc`,
+/// and prints the time per operation of each and the overhead of reading
+/// through weak views. Each is warmed up with an untimed game of its own
+/// first.
+pub fn run_weak_game_benchmark(config: &Config) -> Result<(), BenchError> {
+    println!("Reading through strong handles and through weak views (ns/op)...");
+    let operations = config.total_operations()?;
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "", "strong", "weak", "overhead"
+    );
+    for (name, (strong, weak)) in [
+        ("StdRc", time_weak_game::<StdRcWrapper<Entity>>(config)?),
+        ("CustomRc", time_weak_game::<CustomRc<Entity>>(config)?),
+    ] {
+        println!(
+            "{:<10} {:>12.3} {:>12.3} {:>11.3}x",
+            name,
+            ns_per_operation(strong, operations),
+            ns_per_operation(weak, operations),
+            weak.as_secs_f64() / strong.as_secs_f64()
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// `(Game::run, WeakGame::run)` of two fresh games, with everything the
+/// weak game doesn't do (churn, a timeout, Zipf reads, heavy compute)
+/// turned off.
+fn time_weak_game<RcType: WeakRc<Entity>>(
+    config: &Config,
+) -> Result<(Duration, Duration), BenchError> {
+    let builder = GameBuilder::from_config(&Config {
+        timeout: None,
+        churn_per_frame: 0,
+        zipf: None,
+        heavy_compute: false,
+        ..config.clone()
+    })
+    .quiet(true);
+    black_box(builder.clone().build_weak::<RcType>()?.run());
+    let mut game = builder.clone().build::<RcType>()?;
+    let start = Instant::now();
+    game.run();
+    let strong = start.elapsed();
+    let mut game = builder.build_weak::<RcType>()?;
+    let start = Instant::now();
+    game.run();
+    Ok((strong, start.elapsed()))
+}
+
+/// Times `Game::run_phased` for `StdRc` and `CustomRc` and prints the time
+/// per operation spent cloning and dropping, the clone share of it, and how
+/// much of the whole run neither phase accounts for (the loop and clock
+/// reads around them). Each is warmed up with an untimed game of its own
+/// first.
+pub fn run_clone_drop_benchmark(config: &Config) -> Result<(), BenchError> {
+    println!("Splitting the game into clone and drop phases (ns/op)...");
+    let operations = config.total_operations()?;
+    println!(
+        "{:<10} {:>12} {:>12} {:>12} {:>12}",
+        "", "clone", "drop", "clone share", "unattributed"
+    );
+    for (name, (split, total)) in [
+        ("StdRc", time_clone_drop::<StdRcWrapper<Entity>>(config)?),
+        ("CustomRc", time_clone_drop::<CustomRc<Entity>>(config)?),
+    ] {
+        let unattributed = total.saturating_sub(split.total());
+        println!(
+            "{:<10} {:>12.3} {:>12.3} {:>11.1}% {:>11.1}%",
+            name,
+            ns_per_operation(split.clone, operations),
+            ns_per_operation(split.drop, operations),
+            split.clone_share() * 100.0,
+            unattributed.as_secs_f64() / total.as_secs_f64() * 100.0
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// `run_phased` of a fresh game, and how long the whole call took.
+fn time_clone_drop<RcType: CountedRc<Entity>>(
+    config: &Config,
+) -> Result<(CloneDropSplit, Duration), BenchError> {
+    let builder = GameBuilder::from_config(config).quiet(true);
+    black_box(builder.clone().build::<RcType>()?.run_phased(&SystemClock));
+    let mut game = builder.build::<RcType>()?;
+    let start = Instant::now();
+    let split = game.run_phased(&SystemClock);
+    Ok((split, start.elapsed()))
+}
+
+/// Times dropping a freshly set-up game for `StdRc` and `CustomRc`, once
+/// freeing the entities in the order they were allocated and once in
+/// reverse, and prints the teardown per entity. Each is warmed up with an
+/// untimed game of its own first.
+pub fn run_drop_order_benchmark(config: &Config) -> Result<(), BenchError> {
+    println!("Dropping every entity front to back and back to front (ns/entity)...");
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "", "forward", "reverse", "ratio"
+    );
+    for (name, (forward, reverse)) in [
+        ("StdRc", time_drop_order::<StdRcWrapper<Entity>>(config)?),
+        ("CustomRc", time_drop_order::<CustomRc<Entity>>(config)?),
+    ] {
+        println!(
+            "{:<10} {:>12.3} {:>12.3} {:>11.3}x",
+            name,
+            ns_per_operation(forward, config.num_entities),
+            ns_per_operation(reverse, config.num_entities),
+            reverse.as_secs_f64() / forward.as_secs_f64()
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// Teardown of a forward and of a reversed game. The reversal happens
+/// before the clock starts.
+fn time_drop_order<RcType: CountedRc<Entity>>(
+    config: &Config,
+) -> Result<(Duration, Duration), BenchError> {
+    let builder = GameBuilder::from_config(config).quiet(true);
+    let time = |reversed| {
+        let mut game = builder.clone().build::<RcType>()?;
+        if reversed {
+            game.reverse_entities();
+        }
+        let game = black_box(game);
+        let start = Instant::now();
+        drop(game);
+        Ok::<_, BenchError>(start.elapsed())
+    };
+    black_box(time(false)?);
+    Ok((time(false)?, time(true)?))
+}
+
+/// Times `Game::run` for `StdRc` and `CustomRc` with the entities split
+/// into `archetypes` archetypes, allocated grouped and then interleaved,
+/// and prints how much slower the interleaved layout reads. Each is warmed
+/// up with an untimed game of its own first.
+pub fn run_archetype_benchmark(config: &Config, archetypes: usize) -> Result<(), BenchError> {
+    println!(
+        "Reading {} archetypes allocated grouped and interleaved (ns/op)...",
+        archetypes
+    );
+    let operations = config.total_operations()?;
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "", "grouped", "interleaved", "ratio"
+    );
+    for (name, (grouped, interleaved)) in [
+        (
+            "StdRc",
+            time_archetypes::<StdRcWrapper<Entity>>(config, archetypes)?,
+        ),
+        (
+            "CustomRc",
+            time_archetypes::<CustomRc<Entity>>(config, archetypes)?,
+        ),
+    ] {
+        println!(
+            "{:<10} {:>12.3} {:>12.3} {:>11.3}x",
+            name,
+            ns_per_operation(grouped, operations),
+            ns_per_operation(interleaved, operations),
+            interleaved.as_secs_f64() / grouped.as_secs_f64()
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// `run` of a grouped and of an interleaved game.
+fn time_archetypes<RcType: CountedRc<Entity>>(
+    config: &Config,
+    archetypes: usize,
+) -> Result<(Duration, Duration), BenchError> {
+    let builder = GameBuilder::from_config(config).quiet(true);
+    let time = |grouped| {
+        let mut game = builder
+            .clone()
+            .archetypes(archetypes, grouped)
+            .build::<RcType>()?;
+        let start = Instant::now();
+        game.run();
+        Ok::<_, BenchError>(start.elapsed())
+    };
+    black_box(time(true)?);
+    Ok((time(true)?, time(false)?))
+}
+
+/// Runs `benchmark` with the concrete type behind `implementation`.
+pub fn benchmark_implementation(
+    implementation: Implementation,
+    config: &Config,
+) -> Result<BenchResult, BenchError> {
+    time_implementation(implementation, config, &SystemClock)
+}
+
+fn time_implementation(
+    implementation: Implementation,
+    config: &Config,
+    clock: &dyn Clock,
+) -> Result<BenchResult, BenchError> {
+    let name = implementation.name();
+    match implementation {
+        Implementation::StdRc => time_rc::<StdRcWrapper<Entity>>(name, config, clock),
+        // The atomic-count tax, on a game that never leaves its thread.
+        Implementation::StdArc => time_rc::<StdArcWrapper<Entity>>(name, config, clock),
+        Implementation::CustomRc => time_rc::<CustomRc<Entity>>(name, config, clock),
+        // The Game is single-threaded, so every BiasedArc clone/drop takes the
+        // non-atomic owner path, while CustomArc always pays for atomics.
+        Implementation::CustomArc => time_rc::<CustomArc<Entity>>(name, config, clock),
+        Implementation::BiasedArc => time_rc::<BiasedArc<Entity>>(name, config, clock),
+        // Same arena, same workload; only the handle representation differs.
+        Implementation::ArenaPtr => with_entity_arena(arena_capacity(config)?, || {
+            time_rc::<ArenaRc<PtrRepr>>(name, config, clock)
+        }),
+        Implementation::ArenaIndex => with_entity_arena(arena_capacity(config)?, || {
+            time_rc::<ArenaRc<IndexRepr>>(name, config, clock)
+        }),
+        // ArenaIndex plus a generation check: what use-after-free detection costs.
+        Implementation::ArenaGen => with_entity_arena(arena_capacity(config)?, || {
+            time_rc::<ArenaRc<GenRepr>>(name, config, clock)
+        }),
+        // CustomRc, minus weaks, plus a free list: only differs under `--churn`.
+        Implementation::PooledRc => time_rc::<PooledRc<Entity>>(name, config, clock),
+        // No sharing at all: every clone is an allocation and a copy.
+        Implementation::CopyRc => time_rc::<CopyRc<Entity>>(name, config, clock),
+        // CopyRc without the allocation: the entity fits in the handle.
+        Implementation::SmallRc => time_rc::<SmallRc<Entity>>(name, config, clock),
+        // Not a smart pointer: the locality every handle-based game gives up.
+        Implementation::Soa => time_soa(name, config, clock),
+        // The same counting with no pointer around it: how close CustomRc gets.
+        Implementation::ManualCount => time_manual(name, config, clock),
+        // Copy-in/copy-out against a borrow flag, for each pointer.
+        Implementation::StdRcCell => {
+            time_update::<CellEntity<StdRcWrapper<Cell<Entity>>>>(name, config, clock)
+        }
+        Implementation::CustomRcCell => {
+            time_update::<CellEntity<CustomRc<Cell<Entity>>>>(name, config, clock)
+        }
+        Implementation::StdRcRefCell => {
+            time_update::<RefCellEntity<StdRcWrapper<RefCell<Entity>>>>(name, config, clock)
+        }
+        Implementation::CustomRcRefCell => {
+            time_update::<RefCellEntity<CustomRc<RefCell<Entity>>>>(name, config, clock)
+        }
+        // Only setup differs: every entity comes from a boxed closure.
+        Implementation::StdRcFactory => time_factory::<StdRcWrapper<Entity>>(name, config, clock),
+        Implementation::CustomRcFactory => time_factory::<CustomRc<Entity>>(name, config, clock),
+        // A payload with a destructor: every free also frees the entity's name.
+        Implementation::StdRcNamed => {
+            time_rc::<Named<StdRcWrapper<NamedEntity>>>(name, config, clock)
+        }
+        Implementation::CustomRcNamed => {
+            time_rc::<Named<CustomRc<NamedEntity>>>(name, config, clock)
+        }
+    }
+}
+
+/// Slots the entity arena needs for one game: every entity, plus every
+/// respawn when despawned entities are kept alive by their initial clones.
+fn arena_capacity(config: &Config) -> Result<usize, BenchError> {
+    if config.initial_clones_per_entity == 0 {
+        return Ok(config.num_entities);
+    }
+    config
+        .churn_per_frame
+        .checked_mul(config.num_frames)
+        .and_then(|respawns| respawns.checked_add(config.num_entities))
+        .ok_or(BenchError::Overflow)
+}
+
+// ========================
+// Run and Report Benchmarks
+// ========================
+
+/// Runs the configured implementations and reports on them.
+/// Everything it reports is timed by `C`, the real clock unless swapped out
+/// with `Bencher::clock`.
+#[derive(Debug, Clone)]
+pub struct Bencher<C: Clock = SystemClock> {
+    config: Config,
+    allocator: Option<&'static BenchAllocator>,
+    warm_up: bool,
+    clock: C,
+}
+
+impl Bencher {
+    pub fn new(config: Config) -> Self {
+        Bencher {
+            config,
+            allocator: None,
+            warm_up: true,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> Bencher<C> {
+    /// Times everything with `clock` instead, e.g. a `MockClock` to make
+    /// the reported durations (and where timeouts hit) deterministic.
+    pub fn clock<D: Clock>(self, clock: D) -> Bencher<D> {
+        Bencher {
+            config: self.config,
+            allocator: self.allocator,
+            warm_up: self.warm_up,
+            clock,
+        }
+    }
+
+    /// Whether every implementation gets an untimed game of its own right
+    /// before it's timed (the default).
+    pub fn warm_up(mut self, warm_up: bool) -> Self {
+        self.warm_up = warm_up;
+        self
+    }
+
+    /// The global allocator, so `--alloc-histogram` can track allocation sizes.
+    pub fn allocator(mut self, allocator: &'static BenchAllocator) -> Self {
+        self.allocator = Some(allocator);
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Benchmarks `implementation` and prints its result.
+    pub fn run(&self, implementation: Implementation) -> Result<BenchResult, BenchError> {
+        println!("Benchmarking {}...", implementation.name());
+        let start = self.clock.now();
+        self.warm_up_for(implementation)?;
+        let warm_up = self.warm_up.then(|| self.clock.since(start));
+        let tracker = self.allocator.filter(|_| self.config.alloc_histogram);
+        if let Some(allocator) = tracker {
+            allocator.begin_size_tracking();
+        }
+        let result = self.measure(implementation, &self.config);
+        let allocation_sizes = tracker.map(BenchAllocator::end_size_tracking);
+        let result = BenchResult {
+            warm_up,
+            allocation_sizes,
+            ..result?
+        };
+        if let RunStatus::TimedOut { frames_run } = result.status {
+            println!(
+                "{} timed out after {:?} ({}/{} frames, {}-byte handles)",
+                result.name,
+                result.total(),
+                frames_run,
+                self.config.num_frames,
+                result.handle_size
+            );
+        } else {
+            println!(
+                "{} completed in {:?} ({}-byte handles, {} frames, {} operations/frame, {} extra clones/entity)",
+                result.name,
+                result.total(),
+                result.handle_size,
+                self.config.num_frames,
+                self.config.operations_per_frame,
+                self.config.initial_clones_per_entity
+            );
+        }
+        if self.config.prewarm_blocks > 0 {
+            println!(
+                "allocator pre-warmed with {} blocks before setup",
+                self.config.prewarm_blocks
+            );
+        }
+        println!(
+            "setup {:?}, run {:?} ({:.3} ns/op), drop all {:?}",
+            result.setup,
+            result.run,
+            result.run_per_operation(),
+            result.teardown
+        );
+        if let Some(hot_set) = result.hot_set {
+            println!(
+                "zipf skew {}: {:.0}% of reads hit a hot set of {} entities",
+                self.config.zipf.unwrap_or_default(),
+                HOT_SET_SHARE * 100.0,
+                hot_set
+            );
+        }
+        if let Some(growth) = result.peak_rss_growth {
+            println!("peak RSS grew by {} KiB", growth / 1024);
+        }
+        if let Some(live_handles) = result.live_handles {
+            println!("balance check: OK ({} live handles)", live_handles);
+        }
+        if let Some(dependent) = result.dependent {
+            let operations = self.config.total_operations()?;
+            println!(
+                "dependent access {:?} ({:.2} ns/op, vs {:.2} independent)",
+                dependent,
+                ns_per_operation(dependent, operations),
+                ns_per_operation(result.run, operations)
+            );
+        }
+        if let Some(sizes) = &result.allocation_sizes {
+            println!("Allocation sizes ({} allocations):", sizes.total());
+            print!("{}", sizes);
+        }
+        println!();
+        Ok(result)
+    }
+
+    /// `benchmark_implementation`, timed by this bencher's clock.
+    fn measure(
+        &self,
+        implementation: Implementation,
+        config: &Config,
+    ) -> Result<BenchResult, BenchError> {
+        time_implementation(implementation, config, &self.clock)
+    }
+
+    /// Plays one quiet, untimed game of `implementation` itself. Warming up
+    /// with a single implementation would leave every other one to pay for
+    /// cold code and a cold allocator inside its timed run.
+    fn warm_up_for(&self, implementation: Implementation) -> Result<(), BenchError> {
+        if !self.warm_up {
+            return Ok(());
+        }
+        let config = Config {
+            quiet: true,
+            ..self.config.clone()
+        };
+        self.measure(implementation, &config).map(drop)
+    }
+
+    /// Benchmarks every configured implementation, then prints their run
+    /// times relative to `StdRc`'s, if it was one of them.
+    pub fn run_all(&self) -> Result<Vec<BenchResult>, BenchError> {
+        let results = self
+            .config
+            .implementations
+            .iter()
+            .map(|&implementation| self.run(implementation))
+            .collect::<Result<Vec<_>, _>>()?;
+        print_run_ratios(&results);
+        Ok(results)
+    }
+
+    /// Runs every configured implementation once warm and once with the
+    /// caches flushed, and prints their run throughput side by side.
+    /// Returns the `(warm, cold)` pairs.
+    pub fn cache_report(&self) -> Result<Vec<(BenchResult, BenchResult)>, BenchError> {
+        let warm = Config {
+            flush_cache: false,
+            quiet: true,
+            ..self.config.clone()
+        };
+        let cold = Config {
+            flush_cache: true,
+            ..warm.clone()
+        };
+        let operations = self.config.total_operations()?;
+
+        println!("Comparing warm and cold caches...");
+        println!(
+            "{:<12} {:>14} {:>14} {:>12}",
+            "Mops/s", "warm", "cold", "cold/warm"
+        );
+        let mut results = Vec::new();
+        for &implementation in &self.config.implementations {
+            self.warm_up_for(implementation)?;
+            let warm = self.measure(implementation, &warm)?;
+            let cold = self.measure(implementation, &cold)?;
+            let (warm_rate, cold_rate) = (
+                throughput(operations, warm.run),
+                throughput(operations, cold.run),
+            );
+            println!(
+                "{:<12} {:>14.2} {:>14.2} {:>12.3}",
+                warm.name,
+                warm_rate,
+                cold_rate,
+                cold_rate / warm_rate
+            );
+            results.push((warm, cold));
+        }
+        println!();
+        Ok(results)
+    }
+
+    /// Plays `implementation` over and over, untimed and unreported, until
+    /// `duration` has passed, so that a profiler attached to the process
+    /// sees nothing but the game. Returns how many games were played.
+    pub fn profile(
+        &self,
+        implementation: Implementation,
+        duration: Duration,
+    ) -> Result<usize, BenchError> {
+        let config = Config {
+            quiet: true,
+            ..self.config.clone()
+        };
+        let start = self.clock.now();
+        let mut games = 0;
+        while self.clock.since(start) < duration {
+            black_box(self.measure(implementation, &config)?);
+            games += 1;
+        }
+        Ok(games)
+    }
+
+    /// Times `baseline` and `candidate` `samples` times each (after a
+    /// warm-up) and prints their median run times, the candidate's relative
+    /// to the baseline's. Returns the two medians.
+    pub fn compare(
+        &self,
+        baseline: Implementation,
+        candidate: Implementation,
+    ) -> Result<(Duration, Duration), BenchError> {
+        let config = Config {
+            quiet: true,
+            ..self.config.clone()
+        };
+        let mut medians = [Duration::ZERO; 2];
+        for (median_run, implementation) in medians.iter_mut().zip([baseline, candidate]) {
+            self.warm_up_for(implementation)?;
+            let samples = (0..config.samples)
+                .map(|_| self.measure(implementation, &config))
+                .map(|result| result.map(|result| result.run))
+                .collect::<Result<_, _>>()?;
+            *median_run = median(samples);
+        }
+        let [baseline_run, candidate_run] = medians;
+
+        println!(
+            "Comparing {} against {} (median of {} runs)...",
+            candidate.name(),
+            baseline.name(),
+            config.samples
+        );
+        let operations = config.total_operations()?;
+        println!(
+            "{:<16} {:?} ({:.3} ns/op)",
+            baseline.name(),
+            baseline_run,
+            ns_per_operation(baseline_run, operations)
+        );
+        let ratio = candidate_run.as_secs_f64() / baseline_run.as_secs_f64();
+        println!(
+            "{:<16} {:?} ({:.3} ns/op, {:.3}x the baseline, {:+.1}%)",
+            candidate.name(),
+            candidate_run,
+            ns_per_operation(candidate_run, operations),
+            ratio,
+            (ratio - 1.0) * 100.0
+        );
+        println!();
+        Ok((baseline_run, candidate_run))
+    }
+}
+
+impl<C: Clock + Clone> Bencher<C> {
+    /// Times every configured implementation at every entity count of the
+    /// sweep, `samples` times each (after one warm-up), and collects the
+    /// median run times.
+    pub fn run_matrix(&self) -> Result<Matrix, BenchError> {
+        let entity_counts = self.config.entity_counts();
+        let mut rows = Vec::new();
+        for &implementation in &self.config.implementations {
+            let mut medians = Vec::new();
+            for &num_entities in &entity_counts {
+                let cell = Bencher {
+                    config: Config {
+                        num_entities,
+                        quiet: true,
+                        ..self.config.clone()
+                    },
+                    ..self.clone()
+                };
+                cell.warm_up_for(implementation)?;
+                let samples = (0..self.config.samples)
+                    .map(|_| cell.measure(implementation, &cell.config))
+                    .map(|result| result.map(|result| result.run))
+                    .collect::<Result<_, _>>()?;
+                medians.push(median(samples));
+            }
+            rows.push(MatrixRow {
+                implementation,
+                medians,
+            });
+        }
+        let operations = entity_counts
+            .iter()
+            .map(|&num_entities| {
+                total_operations(
+                    self.config.num_frames,
+                    self.config.operations_per_frame,
+                    num_entities,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Matrix {
+            entity_counts,
+            operations,
+            rows,
+        })
+    }
+
+    /// Times `implementation` over and over, instead of a fixed number of
+    /// `samples`, until the median run time is known to within
+    /// `target_relative_error` (e.g. `0.02` for 2%) or `MAX_STABLE_SAMPLES`
+    /// runs have been taken.
+    pub fn run_until_stable(
+        &self,
+        implementation: Implementation,
+        target_relative_error: f64,
+    ) -> Result<StableSamples, BenchError> {
+        if !(target_relative_error > 0.0 && target_relative_error.is_finite()) {
+            return Err(BenchError::InvalidArgument(format!(
+                "target relative error {}",
+                target_relative_error
+            )));
+        }
+        let config = Config {
+            quiet: true,
+            ..self.config.clone()
+        };
+        self.warm_up_for(implementation)?;
+        sample_until_stable(target_relative_error, MAX_STABLE_SAMPLES, || {
+            self.measure(implementation, &config)
+                .map(|result| result.run)
+        })
+    }
+}
+
+/// Most runs `Bencher::run_until_stable` takes before giving up.
+pub const MAX_STABLE_SAMPLES: usize = 200;
+
+/// The summary after `Bencher::run_all`: every other run time over
+/// `StdRc`'s. A timed-out run didn't do the same work, so it gets no ratio.
+fn print_run_ratios(results: &[BenchResult]) {
+    let baseline_name = Implementation::StdRc.name();
+    let Some(baseline) = results.iter().find(|result| result.name == baseline_name) else {
+        return;
+    };
+    if results.len() < 2 || baseline.status != RunStatus::Completed {
+        return;
+    }
+    println!("Run time relative to {}:", baseline_name);
+    for result in results.iter().filter(|result| result.name != baseline_name) {
+        match result.status {
+            RunStatus::Completed => println!(
+                "{:<16} {:.3}x",
+                result.name,
+                result.run.as_secs_f64() / baseline.run.as_secs_f64()
+            ),
+            RunStatus::TimedOut { .. } => println!("{:<16} timed out", result.name),
+        }
+    }
+    println!();
+}
+
+/// Millions of operations per second.
+pub(crate) fn throughput(operations: usize, duration: Duration) -> f64 {
+    operations as f64 / duration.as_secs_f64() / 1e6
+}
+
+// ========================
+// Hand Handles Across a Boundary
+// ========================
+
+/// Result of `bench_handoff`.
+#[derive(Debug, Clone, Copy)]
+pub struct HandoffTiming {
+    /// Handles handed out and reclaimed, over all rounds.
+    pub handles: usize,
+    /// Handing them out as raw pointers and sharing each once more.
+    pub hand_out: Duration,
+    /// Taking both handles back and dropping them, which frees every value.
+    pub reclaim: Duration,
+}
+
+impl HandoffTiming {
+    /// Nanoseconds for one handle's whole trip out and back.
+    pub fn round_trip(&self) -> f64 {
+        ns_per_operation(self.hand_out + self.reclaim, self.handles)
+    }
+}
+
+/// Models handing entities to a subsystem that only holds raw pointers, as
+/// C would. Each round spawns `num_entities` entities and forgets every
+/// handle through `CustomRc::into_raw`; the other side then shares each
+/// pointer once with `increment_strong_count`, and finally both handles are
+/// reclaimed, one with `decrement_strong_count` and the last with
+/// `from_raw`, and dropped. `num_frames` rounds in all.
+///
+/// Nothing is left anywhere that could free a value but the reclaiming, so
+/// `allocator` counts the round: it fails unless every allocation made was
+/// freed again.
+pub fn bench_handoff(
+    allocator: &BenchAllocator,
+    config: &Config,
+) -> Result<HandoffTiming, BenchError> {
+    let handles = total_operations(config.num_frames, 1, config.num_entities)?;
+    let mut timing = HandoffTiming {
+        handles,
+        hand_out: Duration::ZERO,
+        reclaim: Duration::ZERO,
+    };
+    let mut raw: Vec<*const Entity> = Vec::with_capacity(config.num_entities);
+    allocator.begin_size_tracking();
+    for _ in 0..config.num_frames {
+        let entities: Vec<CustomRc<Entity>> = (0..config.num_entities)
+            .map(|id| {
+                CustomRc::new(Entity {
+                    id,
+                    x: id as f32,
+                    y: 0.0,
+                })
+            })
+            .collect();
+
+        let start = Instant::now();
+        raw.extend(entities.into_iter().map(CustomRc::into_raw));
+        for &entity in &raw {
+            unsafe { CustomRc::increment_strong_count(entity) };
+        }
+        let handed_out = Instant::now();
+        for entity in raw.drain(..) {
+            unsafe {
+                CustomRc::decrement_strong_count(entity);
+                drop(CustomRc::from_raw(black_box(entity)));
+            }
+        }
+        timing.hand_out += handed_out - start;
+        timing.reclaim += handed_out.elapsed();
+    }
+    // `raw` is older than the tracking and has kept its buffer, so anything
+    // still live is an entity.
+    let live = allocator.tracked_live_allocations();
+    allocator.end_size_tracking();
+    if live > 0 {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "{} of {} handed-off entities were never freed",
+            live, handles
+        )));
+    }
+    Ok(timing)
+}
+
+/// Runs `bench_handoff` and prints the cost per handle.
+pub fn run_handoff_benchmark(
+    allocator: &BenchAllocator,
+    config: &Config,
+) -> Result<(), BenchError> {
+    println!(
+        "Handing off CustomRc handles as raw pointers ({} entities, {} rounds)...",
+        config.num_entities, config.num_frames
+    );
+    let timing = bench_handoff(allocator, config)?;
+    println!(
+        "hand out {:?}, reclaim {:?}: {:.2} ns/handle round trip, all {} freed\n",
+        timing.hand_out,
+        timing.reclaim,
+        timing.round_trip(),
+        timing.handles
+    );
+    Ok(())
+}
+
+// ========================
+// Setup Under Different Allocators
+// ========================
+
+/// How many times each setup is timed; the fastest run is reported.
+const SETUP_RUNS: usize = 5;
+
+/// Times `setup` alone for `StdRc` and `CustomRc`, once under the system
+/// allocator and once under a bump arena that is reset between runs, and
+/// prints the resulting 2x2 table. If the rows differ less than the columns,
+/// setup cost is the allocator's, not the Rc's.
+pub fn compare_setup_allocators(
+    allocator: &BenchAllocator,
+    config: &Config,
+) -> Result<(), BenchError> {
+    println!("Comparing setup under the system and bump allocators...");
+    let rows = [
+        (
+            "StdRc",
+            time_setup::<StdRcWrapper<Entity>>(allocator, config, false)?,
+            time_setup::<StdRcWrapper<Entity>>(allocator, config, true)?,
+        ),
+        (
+            "CustomRc",
+            time_setup::<CustomRc<Entity>>(allocator, config, false)?,
+            time_setup::<CustomRc<Entity>>(allocator, config, true)?,
+        ),
+    ];
+    println!("{:<10} {:>14} {:>14}", "setup", "system", "bump");
+    for (name, system, bump) in rows {
+        println!(
+            "{:<10} {:>14} {:>14}",
+            name,
+            format!("{:?}", system),
+            format!("{:?}", bump)
+        );
+    }
+    println!();
+    Ok(())
+}
+
+fn time_setup<RcType>(
+    allocator: &BenchAllocator,
+    config: &Config,
+    bump: bool,
+) -> Result<Duration, BenchError>
+where
+    RcType: CountedRc<Entity>,
+{
+    let builder = GameBuilder::from_config(config);
+    let mut best = Duration::MAX;
+    for _ in 0..SETUP_RUNS {
+        if bump {
+            allocator.begin_bump(bump_capacity::<RcType>(config)?)?;
+        } else {
+            prewarm_allocator(config.prewarm_blocks);
+        }
+        let start = Instant::now();
+        let game = builder.clone().build::<RcType>();
+        let duration = start.elapsed();
+        // Everything allocated from the arena has to be gone before it's released.
+        let built = game.map(drop);
+        if bump {
+            allocator.end_bump();
+        }
+        built?;
+        best = best.min(duration);
+    }
+    Ok(best)
+}
+
+/// Generous arena size for one setup of `config`: the shared allocations
+/// plus the `entities` and `retained` vectors, each reserved once to its
+/// exact length. `retained` holds the `initial_clones_per_entity` clones of
+/// every entity and at most one more for each entity `retain_rate` picks.
+fn bump_capacity<RcType>(config: &Config) -> Result<usize, BenchError> {
+    let num_entities = config.num_entities;
+    let picked = (num_entities as f64 * config.retain_rate.clamp(0.0, 1.0)).ceil() as usize;
+    let value_size = std::mem::size_of::<Entity>() + 4 * std::mem::size_of::<usize>();
+    num_entities
+        .checked_mul(config.initial_clones_per_entity)
+        .and_then(|retained| retained.checked_add(picked))
+        .and_then(|retained| retained.checked_add(num_entities))
+        .and_then(|handles| handles.checked_mul(std::mem::size_of::<RcType>()))
+        .and_then(|handles| handles.checked_add(num_entities.checked_mul(value_size)?))
+        .and_then(|capacity| capacity.checked_add(1 << 20))
+        .ok_or(BenchError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    /// A config small enough that every test game finishes instantly.
+    fn tiny(implementations: &[Implementation]) -> Config {
+        Config {
+            num_entities: 8,
+            num_frames: 2,
+            operations_per_frame: 2,
+            implementations: implementations.to_vec(),
+            quiet: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn the_cache_report_runs_warm_and_cold() {
+        let config = tiny(&[Implementation::CustomRc, Implementation::ArenaIndex]);
+        let report = Bencher::new(config).warm_up(false).cache_report().unwrap();
+        assert_eq!(report.len(), 2);
+        for (warm, cold) in &report {
+            assert_eq!(warm.name, cold.name);
+            assert_eq!(
+                (warm.status, cold.status),
+                (RunStatus::Completed, RunStatus::Completed)
+            );
+            assert_eq!(warm.operations, 32);
+            assert_eq!(cold.operations, 32);
+        }
+    }
+
+    #[test]
+    fn a_timeout_stops_the_game_between_frames() {
+        let config = Config {
+            num_frames: 10,
+            timeout: Some(Duration::from_millis(3)),
+            ..tiny(&[Implementation::CustomRc])
+        };
+        let result = Bencher::new(config)
+            .warm_up(false)
+            .clock(MockClock::new(Duration::from_millis(1)))
+            .run(Implementation::CustomRc)
+            .unwrap();
+        // The deadline is read once, then once before every later frame.
+        assert_eq!(result.status, RunStatus::TimedOut { frames_run: 3 });
+        assert_eq!(result.operations, 3 * 2 * 8);
+    }
+
+    #[test]
+    fn the_matrix_covers_every_implementation_at_every_count() {
+        let config = Config {
+            sweep_entities: vec![4, 8],
+            samples: 3,
+            ..tiny(&[Implementation::StdRc, Implementation::CustomRc])
+        };
+        let matrix = Bencher::new(config)
+            .clock(MockClock::new(Duration::from_millis(1)))
+            .run_matrix()
+            .unwrap();
+        assert_eq!(matrix.entity_counts, [4, 8]);
+        assert_eq!(matrix.operations, [16, 32]);
+        let implementations: Vec<_> = matrix.rows.iter().map(|row| row.implementation).collect();
+        assert_eq!(
+            implementations,
+            [Implementation::StdRc, Implementation::CustomRc]
+        );
+        for row in &matrix.rows {
+            // One clock step between the two reads around each run.
+            assert_eq!(row.medians, [Duration::from_millis(1); 2]);
+        }
+    }
+
+    #[test]
+    fn steady_runs_are_stable_after_the_minimum_samples() {
+        let bencher = Bencher::new(tiny(&[Implementation::CustomRc]))
+            .warm_up(false)
+            .clock(MockClock::new(Duration::from_millis(1)));
+        let stable = bencher
+            .run_until_stable(Implementation::CustomRc, 0.01)
+            .unwrap();
+        assert!(stable.converged);
+        assert_eq!(stable.samples, [Duration::from_millis(1); 5]);
+        for target in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                bencher.run_until_stable(Implementation::CustomRc, target),
+                Err(BenchError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn a_mock_clock_makes_every_duration_one_step() {
+        let config = Config {
+            dependent_access: true,
+            ..tiny(&[Implementation::CustomRc])
+        };
+        let step = Duration::from_millis(1);
+        let result = Bencher::new(config)
+            .warm_up(false)
+            .clock(MockClock::new(step))
+            .run(Implementation::CustomRc)
+            .unwrap();
+        assert_eq!(result.status, RunStatus::Completed);
+        assert_eq!(
+            (result.setup, result.run, result.dependent, result.teardown),
+            (step, step, Some(step), step)
+        );
+        assert_eq!(result.warm_up, None);
+    }
+
+    #[test]
+    fn the_bump_arena_makes_room_for_retained_clones() {
+        let config = Config {
+            num_entities: 1000,
+            initial_clones_per_entity: 0,
+            retain_rate: 0.0,
+            ..Config::default()
+        };
+        let handle = std::mem::size_of::<CustomRc<Entity>>();
+        let bare = bump_capacity::<CustomRc<Entity>>(&config).unwrap();
+        let cloned = bump_capacity::<CustomRc<Entity>>(&Config {
+            initial_clones_per_entity: 3,
+            retain_rate: 0.25,
+            ..config.clone()
+        })
+        .unwrap();
+        assert_eq!(cloned - bare, (3000 + 250) * handle);
+        assert_eq!(
+            bump_capacity::<CustomRc<Entity>>(&Config {
+                initial_clones_per_entity: usize::MAX,
+                ..config
+            }),
+            Err(BenchError::Overflow)
+        );
+    }
+
+    #[test]
+    fn every_result_counts_its_operations() {
+        let result = Bencher::new(tiny(&[Implementation::StdRc]))
+            .warm_up(false)
+            .clock(MockClock::new(Duration::from_micros(64)))
+            .run(Implementation::StdRc)
+            .unwrap();
+        // 2 frames x 2 ops x 8 entities over one 64us step.
+        assert_eq!(result.operations, 32);
+        assert_eq!(result.run_per_operation(), 2000.0);
+    }
+}
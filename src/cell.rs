@@ -0,0 +1,69 @@
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+
+use crate::entity::Entity;
+use crate::rc::Constructor;
+
+// ========================
+// Shared, Mutable Entities
+// ========================
+
+/// A cloneable handle through which a shared entity is updated in place.
+pub trait SharedEntity: Clone {
+    fn new(entity: Entity) -> Self;
+
+    /// A copy of the entity as it is now.
+    fn get(&self) -> Entity;
+
+    /// Runs `Entity::update` on the shared entity and returns the result.
+    fn update(&self) -> Entity;
+}
+
+/// An entity behind `P<Cell<Entity>>`: no borrow flag, but every update
+/// copies the whole entity out and back in.
+#[derive(Clone)]
+pub struct CellEntity<P>(P);
+
+impl<P> SharedEntity for CellEntity<P>
+where
+    P: Clone + Deref<Target = Cell<Entity>> + Constructor<Cell<Entity>>,
+{
+    fn new(entity: Entity) -> Self {
+        CellEntity(P::new(Cell::new(entity)))
+    }
+
+    fn get(&self) -> Entity {
+        self.0.get()
+    }
+
+    fn update(&self) -> Entity {
+        let mut entity = self.0.get();
+        entity.update();
+        self.0.set(entity);
+        entity
+    }
+}
+
+/// An entity behind `P<RefCell<Entity>>`: updated through a `&mut`, after
+/// checking and setting the borrow flag.
+#[derive(Clone)]
+pub struct RefCellEntity<P>(P);
+
+impl<P> SharedEntity for RefCellEntity<P>
+where
+    P: Clone + Deref<Target = RefCell<Entity>> + Constructor<RefCell<Entity>>,
+{
+    fn new(entity: Entity) -> Self {
+        RefCellEntity(P::new(RefCell::new(entity)))
+    }
+
+    fn get(&self) -> Entity {
+        *self.0.borrow()
+    }
+
+    fn update(&self) -> Entity {
+        let mut entity = self.0.borrow_mut();
+        entity.update();
+        *entity
+    }
+}
@@ -0,0 +1,74 @@
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+// ========================
+// Abstract the Clock
+// ========================
+
+/// Where timings come from. Everything the `Bencher` reports, and every game
+/// timeout, is measured against one of these.
+pub trait Clock: Debug {
+    fn now(&self) -> Instant;
+
+    /// Time passed since `start`, by this clock.
+    fn since(&self, start: Instant) -> Duration {
+        self.now().saturating_duration_since(start)
+    }
+}
+
+/// The real clock, `Instant::now`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that advances by exactly `step` every time it's read, so anything
+/// timed with it comes out as a whole number of steps.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    step: Duration,
+    reads: Cell<u32>,
+}
+
+impl MockClock {
+    pub fn new(step: Duration) -> Self {
+        MockClock {
+            start: Instant::now(),
+            step,
+            reads: Cell::new(0),
+        }
+    }
+
+    /// How many times the clock has been read.
+    pub fn reads(&self) -> u32 {
+        self.reads.get()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        let reads = self.reads.get();
+        self.reads.set(reads + 1);
+        self.start + self.step * reads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_mock_clock_advances_one_step_per_read() {
+        let clock = MockClock::new(Duration::from_millis(2));
+        let start = clock.now();
+        assert_eq!(clock.since(start), Duration::from_millis(2));
+        assert_eq!(clock.now() - start, Duration::from_millis(4));
+        assert_eq!(clock.reads(), 3);
+    }
+}
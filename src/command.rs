@@ -0,0 +1,218 @@
+//! The binary's subcommands. Each takes the flags `Config::from_args`
+//! understands, plus a few of its own.
+
+use std::time::Duration;
+
+use crate::config::{parse_timeout, Config, Implementation};
+use crate::error::BenchError;
+
+/// How long `profile` keeps playing unless told otherwise.
+pub const DEFAULT_PROFILE_DURATION: Duration = Duration::from_secs(10);
+
+/// What the binary was asked to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `run [FLAGS]`: benchmark the configured implementations once each.
+    /// Also what happens without a subcommand.
+    Run(Config),
+    /// `sweep [--entities A,B,...] [FLAGS]`: the entity-count matrix.
+    Sweep(Config),
+    /// `profile IMPL [--seconds S] [FLAGS]`: play one implementation over
+    /// and over for `duration`, with nothing else running, for a profiler
+    /// to sample.
+    Profile {
+        config: Config,
+        implementation: Implementation,
+        duration: Duration,
+    },
+    /// `compare A B [FLAGS]`: time `baseline` and `candidate` `samples`
+    /// times each and report the candidate relative to the baseline.
+    Compare {
+        config: Config,
+        baseline: Implementation,
+        candidate: Implementation,
+    },
+}
+
+impl Command {
+    /// The configuration the subcommand runs with.
+    pub fn config(&self) -> &Config {
+        match self {
+            Command::Run(config) | Command::Sweep(config) => config,
+            Command::Profile { config, .. } | Command::Compare { config, .. } => config,
+        }
+    }
+
+    /// Parses the arguments (without the program name). If the first one
+    /// isn't a subcommand they're all flags for `run`.
+    pub fn from_args<I>(args: I) -> Result<Self, BenchError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter().peekable();
+        let subcommand = match args.peek() {
+            Some(arg) if !arg.starts_with("--") => args.next(),
+            _ => None,
+        };
+        match subcommand.as_deref() {
+            None | Some("run") => Config::from_args(args).map(Command::Run),
+            Some("sweep") => parse_sweep(args),
+            Some("profile") => {
+                let implementation = parse_implementation(args.next(), "profile")?;
+                let mut duration = DEFAULT_PROFILE_DURATION;
+                let mut rest = Vec::new();
+                while let Some(arg) = args.next() {
+                    if arg == "--seconds" {
+                        duration =
+                            parse_timeout(args.next()).ok_or(BenchError::InvalidArgument(arg))?;
+                    } else {
+                        rest.push(arg);
+                    }
+                }
+                let config = Config {
+                    implementations: vec![implementation],
+                    quiet: true,
+                    ..Config::from_args(rest)?
+                };
+                Ok(Command::Profile {
+                    config,
+                    implementation,
+                    duration,
+                })
+            }
+            Some("compare") => {
+                let baseline = parse_implementation(args.next(), "compare")?;
+                let candidate = parse_implementation(args.next(), "compare")?;
+                let config = Config {
+                    implementations: vec![baseline, candidate],
+                    ..Config::from_args(args)?
+                };
+                Ok(Command::Compare {
+                    config,
+                    baseline,
+                    candidate,
+                })
+            }
+            Some(other) => Err(BenchError::InvalidArgument(other.to_string())),
+        }
+    }
+}
+
+/// In a sweep, `--entities` takes the whole list of counts, as
+/// `--sweep-entities` does everywhere.
+fn parse_sweep(args: impl Iterator<Item = String>) -> Result<Command, BenchError> {
+    let args: Vec<String> = args
+        .map(|arg| match arg.as_str() {
+            "--entities" => "--sweep-entities".to_string(),
+            _ => arg,
+        })
+        .collect();
+    let config = Config::from_args(args)?;
+    if config.sweep_entities.is_empty() {
+        return Err(BenchError::InvalidArgument(
+            "sweep (give the counts with --entities A,B,...)".to_string(),
+        ));
+    }
+    Ok(Command::Sweep(config))
+}
+
+/// The positional implementation name `subcommand` expects next.
+fn parse_implementation(
+    value: Option<String>,
+    subcommand: &str,
+) -> Result<Implementation, BenchError> {
+    match value {
+        Some(name) if !name.starts_with("--") => Implementation::from_name(&name),
+        _ => Err(BenchError::InvalidArgument(format!(
+            "{} (expected an implementation name)",
+            subcommand
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command, BenchError> {
+        Command::from_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn bare_flags_are_a_run() {
+        let command = parse("--entities 10").unwrap();
+        assert_eq!(
+            command,
+            Command::Run(Config {
+                num_entities: 10,
+                ..Config::default()
+            })
+        );
+        assert_eq!(parse("run --entities 10").unwrap(), command);
+        assert_eq!(parse("").unwrap(), Command::Run(Config::default()));
+    }
+
+    #[test]
+    fn sweep_entities_take_the_whole_list() {
+        let Command::Sweep(config) = parse("sweep --entities 10,20 --frames 3").unwrap() else {
+            panic!("not a sweep");
+        };
+        assert_eq!(config.sweep_entities, [10, 20]);
+        assert_eq!(config.num_frames, 3);
+        assert!(matches!(
+            parse("sweep"),
+            Err(BenchError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn profile_and_compare_take_implementation_names() {
+        assert_eq!(
+            parse("profile customrc --seconds 2").unwrap(),
+            Command::Profile {
+                config: Config {
+                    implementations: vec![Implementation::CustomRc],
+                    quiet: true,
+                    ..Config::default()
+                },
+                implementation: Implementation::CustomRc,
+                duration: Duration::from_secs(2),
+            }
+        );
+        let command = parse("compare StdRc CustomRc --samples 3").unwrap();
+        let Command::Compare {
+            config,
+            baseline,
+            candidate,
+        } = command
+        else {
+            panic!("not a compare");
+        };
+        assert_eq!(
+            (baseline, candidate),
+            (Implementation::StdRc, Implementation::CustomRc)
+        );
+        assert_eq!(config.samples, 3);
+        assert_eq!(config.implementations, [baseline, candidate]);
+    }
+
+    #[test]
+    fn missing_or_unknown_names_are_refused() {
+        assert!(matches!(
+            parse("profile"),
+            Err(BenchError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            parse("compare StdRc --samples 3"),
+            Err(BenchError::InvalidArgument(_))
+        ));
+        assert_eq!(
+            parse("profile NoRc").err(),
+            Some(BenchError::UnknownImpl("NoRc".to_string()))
+        );
+        assert_eq!(
+            parse("bench").err(),
+            Some(BenchError::InvalidArgument("bench".to_string()))
+        );
+    }
+}
@@ -0,0 +1,948 @@
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::BenchError;
+
+/// The smart pointer implementations the benchmark knows how to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    StdRc,
+    /// `std::sync::Arc`, on the same single-threaded game.
+    StdArc,
+    CustomRc,
+    CustomArc,
+    BiasedArc,
+    ArenaPtr,
+    ArenaIndex,
+    /// Arena index handles that check the slot's generation on every read.
+    ArenaGen,
+    PooledRc,
+    /// Deep-copies the entity on every clone instead of sharing it.
+    CopyRc,
+    /// Keeps an entity as small as `Entity` inline in the handle, unshared.
+    SmallRc,
+    /// Struct-of-arrays columns instead of refcounted entities.
+    Soa,
+    /// Counts kept by hand in a plain array next to the entities.
+    ManualCount,
+    /// `StdRc<Cell<Entity>>`, updating every entity it reads.
+    StdRcCell,
+    CustomRcCell,
+    /// `StdRc<RefCell<Entity>>`, updating every entity it reads.
+    StdRcRefCell,
+    CustomRcRefCell,
+    /// `StdRc`, set up through a boxed `Factory` instead of `Constructor::new`.
+    StdRcFactory,
+    CustomRcFactory,
+    /// `StdRc` to an entity with a heap-allocated name, which the last drop
+    /// frees too.
+    StdRcNamed,
+    CustomRcNamed,
+}
+
+impl Implementation {
+    pub const ALL: [Implementation; 21] = [
+        Implementation::StdRc,
+        Implementation::StdArc,
+        Implementation::CustomRc,
+        Implementation::CustomArc,
+        Implementation::BiasedArc,
+        Implementation::ArenaPtr,
+        Implementation::ArenaIndex,
+        Implementation::ArenaGen,
+        Implementation::PooledRc,
+        Implementation::CopyRc,
+        Implementation::SmallRc,
+        Implementation::Soa,
+        Implementation::ManualCount,
+        Implementation::StdRcCell,
+        Implementation::CustomRcCell,
+        Implementation::StdRcRefCell,
+        Implementation::CustomRcRefCell,
+        Implementation::StdRcFactory,
+        Implementation::CustomRcFactory,
+        Implementation::StdRcNamed,
+        Implementation::CustomRcNamed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Implementation::StdRc => "StdRc",
+            Implementation::StdArc => "StdArc",
+            Implementation::CustomRc => "CustomRc",
+            Implementation::CustomArc => "CustomArc",
+            Implementation::BiasedArc => "BiasedArc",
+            Implementation::ArenaPtr => "ArenaPtr",
+            Implementation::ArenaIndex => "ArenaIndex",
+            Implementation::ArenaGen => "ArenaGen",
+            Implementation::PooledRc => "PooledRc",
+            Implementation::CopyRc => "CopyRc",
+            Implementation::SmallRc => "SmallRc",
+            Implementation::Soa => "SoA",
+            Implementation::ManualCount => "ManualCount",
+            Implementation::StdRcCell => "StdRcCell",
+            Implementation::CustomRcCell => "CustomRcCell",
+            Implementation::StdRcRefCell => "StdRcRefCell",
+            Implementation::CustomRcRefCell => "CustomRcRefCell",
+            Implementation::StdRcFactory => "StdRcFactory",
+            Implementation::CustomRcFactory => "CustomRcFactory",
+            Implementation::StdRcNamed => "StdRcNamed",
+            Implementation::CustomRcNamed => "CustomRcNamed",
+        }
+    }
+
+    /// Looks an implementation up by its (case-insensitive) name.
+    pub fn from_name(name: &str) -> Result<Self, BenchError> {
+        Implementation::ALL
+            .into_iter()
+            .find(|implementation| implementation.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| BenchError::UnknownImpl(name.to_string()))
+    }
+
+    /// Looks up a list of names, where `all` (in any case) stands for every
+    /// implementation, in `ALL` order.
+    pub fn from_names<'a>(
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Self>, BenchError> {
+        let mut implementations = Vec::new();
+        for name in names {
+            if name.eq_ignore_ascii_case("all") {
+                implementations.extend(Implementation::ALL);
+            } else {
+                implementations.push(Implementation::from_name(name)?);
+            }
+        }
+        Ok(implementations)
+    }
+}
+
+/// How the benchmark matrix is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned plain-text columns.
+    Text,
+    /// A GitHub-flavored markdown table.
+    Markdown,
+}
+
+impl OutputFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Markdown => "markdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [OutputFormat::Text, OutputFormat::Markdown]
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Benchmark settings, usually parsed from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub num_entities: usize,         // Number of entities in the game
+    pub num_frames: usize,           // Number of frames to simulate
+    pub operations_per_frame: usize, // Number of operations per frame
+    pub implementations: Vec<Implementation>,
+    /// Time only `setup`, under the system allocator and a bump arena.
+    pub compare_allocators: bool,
+    /// Follow each pass over the entities with `Entity::update_batch`.
+    pub heavy_compute: bool,
+    /// Extra clones of every entity held for the whole game.
+    pub initial_clones_per_entity: usize,
+    /// Share of the entities that get one more clone held for the whole game.
+    pub retain_rate: f64,
+    /// Suppress the per-frame progress lines.
+    pub quiet: bool,
+    /// Run the micro-benchmarks instead of the game.
+    pub micro: bool,
+    /// Time handing handles out as raw pointers and reclaiming them instead.
+    pub handoff: bool,
+    /// Time the game with and without cloning every read instead.
+    pub clone_tax: bool,
+    /// Time dropping the entities in allocation order and in reverse instead.
+    pub drop_order: bool,
+    /// Split the game into timed clone and drop phases instead.
+    pub clone_drop_split: bool,
+    /// Time the game read through weak views of its entities instead.
+    pub weak_game: bool,
+    /// Compare grouped and interleaved allocation of this many entity
+    /// archetypes instead.
+    pub archetypes: Option<usize>,
+    /// Widest gap between consecutive entity ids; 0 keeps them contiguous.
+    pub max_id_gap: usize,
+    /// Seed for everything randomized.
+    pub seed: u64,
+    /// Run this many random operations through the equivalence harness instead.
+    pub fuzz_steps: Option<usize>,
+    /// Run this many steps of clone churn across nested scopes instead.
+    pub stress_steps: Option<usize>,
+    /// Run the parallel CustomArc benchmark on this many threads instead.
+    pub threads: Option<usize>,
+    /// Pin each parallel benchmark thread to its own core.
+    pub pin_threads: bool,
+    /// Stress concurrent CustomArc reads on this many threads instead.
+    pub stress_arc: Option<usize>,
+    /// Race the stressed reads against a writer, to check the stress catches
+    /// it. Undefined behaviour on purpose, so it needs the `race-demo` feature.
+    pub stress_arc_race: bool,
+    /// Flush the caches between setup and each timed run.
+    pub flush_cache: bool,
+    /// Time every implementation both warm and cold instead.
+    pub cache_report: bool,
+    /// Print a histogram of the allocation sizes of every benchmark.
+    pub alloc_histogram: bool,
+    /// Stop a benchmarked game after the frame during which this runs out.
+    pub timeout: Option<Duration>,
+    /// Entities despawned and respawned at the end of every frame.
+    pub churn_per_frame: usize,
+    /// Blocks the size of an entity's allocation to allocate and free right
+    /// before every setup, so it starts from warm free lists.
+    pub prewarm_blocks: usize,
+    /// Entity counts to run every implementation at, instead of just
+    /// `num_entities`, for the benchmark matrix.
+    pub sweep_entities: Vec<usize>,
+    /// Timed runs per matrix cell; the median is reported.
+    pub samples: usize,
+    /// How the benchmark matrix is printed.
+    pub format: OutputFormat,
+    /// Also time a pointer-chasing pass, where every read depends on the last.
+    pub dependent_access: bool,
+    /// Skew every pass's reads towards a hot set, with this Zipf exponent.
+    pub zipf: Option<f64>,
+    /// Run the get_mut-or-read workload instead, with this fraction of the
+    /// entities shared.
+    pub mut_or_read: Option<f64>,
+    /// Run the weak upgrade storm instead, despawning this fraction of the
+    /// entities every frame.
+    pub upgrade_storm: Option<f64>,
+    /// Write a Chrome trace of every implementation's phases to this file
+    /// (after a plain run, not a matrix or cache report).
+    pub trace: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            num_entities: 25_000,
+            num_frames: 25,
+            operations_per_frame: 10_000,
+            implementations: vec![Implementation::StdRc, Implementation::CustomRc],
+            compare_allocators: false,
+            heavy_compute: false,
+            initial_clones_per_entity: 0,
+            retain_rate: 0.0,
+            quiet: false,
+            micro: false,
+            handoff: false,
+            clone_tax: false,
+            drop_order: false,
+            clone_drop_split: false,
+            weak_game: false,
+            archetypes: None,
+            max_id_gap: 0,
+            seed: 1,
+            fuzz_steps: None,
+            stress_steps: None,
+            threads: None,
+            pin_threads: false,
+            stress_arc: None,
+            stress_arc_race: false,
+            flush_cache: false,
+            cache_report: false,
+            alloc_histogram: false,
+            timeout: None,
+            churn_per_frame: 0,
+            prewarm_blocks: 0,
+            sweep_entities: Vec::new(),
+            samples: 1,
+            format: OutputFormat::Text,
+            dependent_access: false,
+            zipf: None,
+            mut_or_read: None,
+            upgrade_storm: None,
+            trace: None,
+        }
+    }
+}
+
+impl Config {
+    /// Total clone-and-read operations of one game:
+    /// `frames * operations_per_frame * entities`.
+    pub fn total_operations(&self) -> Result<usize, BenchError> {
+        total_operations(
+            self.num_frames,
+            self.operations_per_frame,
+            self.num_entities,
+        )
+    }
+
+    /// The entity counts of the benchmark matrix: `sweep_entities`, or just
+    /// `num_entities` when no sweep was asked for.
+    pub fn entity_counts(&self) -> Vec<usize> {
+        if self.sweep_entities.is_empty() {
+            vec![self.num_entities]
+        } else {
+            self.sweep_entities.clone()
+        }
+    }
+
+    /// Fails with `Overflow` if any game this config describes is too big,
+    /// or with `InvalidArgument` if it asks for more than one mode.
+    fn validate(&self) -> Result<(), BenchError> {
+        if let [first, second, ..] = self.modes()[..] {
+            return Err(BenchError::InvalidArgument(format!(
+                "{} (conflicts with {})",
+                second, first
+            )));
+        }
+        for entities in self.entity_counts() {
+            total_operations(self.num_frames, self.operations_per_frame, entities)?;
+        }
+        self.total_operations().map(drop)
+    }
+
+    /// The flags of every mode this config selects. Each mode replaces the
+    /// plain run of the implementations, so at most one may be set.
+    fn modes(&self) -> Vec<&'static str> {
+        [
+            ("--compare-allocators", self.compare_allocators),
+            ("--micro", self.micro),
+            ("--archetypes", self.archetypes.is_some()),
+            ("--clone-tax", self.clone_tax),
+            ("--clone-drop-split", self.clone_drop_split),
+            ("--weak-game", self.weak_game),
+            ("--drop-order", self.drop_order),
+            ("--handoff", self.handoff),
+            ("--mut-or-read", self.mut_or_read.is_some()),
+            ("--upgrade-storm", self.upgrade_storm.is_some()),
+            ("--threads", self.threads.is_some()),
+            ("--stress-arc", self.stress_arc.is_some()),
+            ("--fuzz-equivalence", self.fuzz_steps.is_some()),
+            ("--stress-scopes", self.stress_steps.is_some()),
+            ("--sweep-entities", !self.sweep_entities.is_empty()),
+            (
+                "--format markdown",
+                self.format == OutputFormat::Markdown && self.sweep_entities.is_empty(),
+            ),
+            ("--cache-report", self.cache_report),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect()
+    }
+
+    /// Parses `--entities N`, `--frames N`, `--ops N`, `--impl A,B,...|all`,
+    /// `--initial-clones N`, `--retain-rate FRACTION`, `--compare-allocators`,
+    /// `--heavy-compute`, `--quiet`, `--micro`, `--handoff`, `--clone-tax`,
+    /// `--drop-order`, `--clone-drop-split`, `--weak-game`, `--seed N`,
+    /// `--fuzz-equivalence STEPS`, `--stress-scopes STEPS`, `--threads N`,
+    /// `--pin-threads`, `--stress-arc THREADS`, `--stress-arc-race`,
+    /// `--flush-cache`, `--cache-report`, `--alloc-histogram`,
+    /// `--dependent-access`, `--zipf SKEW`, `--mut-or-read FRACTION`,
+    /// `--upgrade-storm FRACTION`, `--timeout SECONDS`, `--churn N`,
+    /// `--prewarm-allocator N`, `--archetypes N`, `--id-gaps MAX`,
+    /// `--sweep-entities A,B,...`, `--samples N`, `--format text|markdown`,
+    /// `--trace FILE` and `--config FILE` (without the program name). Anything
+    /// not given keeps its default. Arguments apply in order, so flags after
+    /// `--config` override the file. Flags picking a mode other than the
+    /// plain run (`--micro`, `--threads N`, a sweep, ...) exclude each other.
+    pub fn from_args<I>(args: I) -> Result<Self, BenchError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--entities" => {
+                    config.num_entities =
+                        parse_count(args.next()).ok_or(BenchError::InvalidEntities)?
+                }
+                "--frames" => {
+                    config.num_frames = parse_count(args.next()).ok_or(BenchError::InvalidFrames)?
+                }
+                "--ops" => config.operations_per_frame = parse_value(args.next(), arg)?,
+                "--impl" => {
+                    let value = args.next().ok_or(BenchError::InvalidArgument(arg))?;
+                    config.implementations = Implementation::from_names(value.split(','))?;
+                }
+                "--initial-clones" => {
+                    config.initial_clones_per_entity = parse_value(args.next(), arg)?
+                }
+                "--retain-rate" => {
+                    config.retain_rate =
+                        parse_fraction(args.next()).ok_or(BenchError::InvalidArgument(arg))?
+                }
+                "--compare-allocators" => config.compare_allocators = true,
+                "--heavy-compute" => config.heavy_compute = true,
+                "--quiet" => config.quiet = true,
+                "--micro" => config.micro = true,
+                "--handoff" => config.handoff = true,
+                "--clone-tax" => config.clone_tax = true,
+                "--drop-order" => config.drop_order = true,
+                "--clone-drop-split" => config.clone_drop_split = true,
+                "--weak-game" => config.weak_game = true,
+                "--archetypes" => {
+                    config.archetypes =
+                        Some(parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--id-gaps" => config.max_id_gap = parse_value(args.next(), arg)?,
+                "--seed" => config.seed = parse_value(args.next(), arg)?,
+                "--fuzz-equivalence" => {
+                    config.fuzz_steps =
+                        Some(parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--stress-scopes" => {
+                    config.stress_steps =
+                        Some(parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--threads" => {
+                    config.threads =
+                        Some(parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--pin-threads" => config.pin_threads = true,
+                "--stress-arc" => {
+                    config.stress_arc =
+                        Some(parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--stress-arc-race" => config.stress_arc_race = true,
+                "--flush-cache" => config.flush_cache = true,
+                "--cache-report" => config.cache_report = true,
+                "--alloc-histogram" => config.alloc_histogram = true,
+                "--dependent-access" => config.dependent_access = true,
+                "--sweep-entities" => {
+                    let value = args.next().ok_or(BenchError::InvalidArgument(arg))?;
+                    config.sweep_entities = value
+                        .split(',')
+                        .map(|count| parse_count(Some(count.to_string())))
+                        .collect::<Option<_>>()
+                        .ok_or(BenchError::InvalidEntities)?;
+                }
+                "--samples" => {
+                    config.samples =
+                        parse_count(args.next()).ok_or(BenchError::InvalidArgument(arg))?
+                }
+                "--format" => {
+                    config.format = args
+                        .next()
+                        .and_then(|name| OutputFormat::from_name(&name))
+                        .ok_or(BenchError::InvalidArgument(arg))?
+                }
+                "--churn" => config.churn_per_frame = parse_value(args.next(), arg)?,
+                "--prewarm-allocator" => config.prewarm_blocks = parse_value(args.next(), arg)?,
+                "--zipf" => {
+                    config.zipf =
+                        Some(parse_skew(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--mut-or-read" => {
+                    config.mut_or_read =
+                        Some(parse_fraction(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--upgrade-storm" => {
+                    config.upgrade_storm =
+                        Some(parse_fraction(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--trace" => {
+                    config.trace = Some(args.next().ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--timeout" => {
+                    config.timeout =
+                        Some(parse_timeout(args.next()).ok_or(BenchError::InvalidArgument(arg))?)
+                }
+                "--config" => {
+                    let path = args.next().ok_or(BenchError::InvalidArgument(arg))?;
+                    config.apply_file(&fs::read_to_string(path)?)?;
+                }
+                _ => return Err(BenchError::InvalidArgument(arg)),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+// ========================
+// Load a Config File
+// ========================
+
+impl Config {
+    /// Reads a config file: TOML `key = value` lines named after the fields of
+    /// `Config`, holding integers, booleans, strings or (for
+    /// `implementations`) an array of names, `"all"` among them standing for
+    /// every implementation, with `#` comments. Keys that are
+    /// absent keep their defaults.
+    ///
+    /// Only this flat subset of TOML is understood; tables, multi-line arrays
+    /// and literal strings are rejected as invalid lines. Basic strings may
+    /// use TOML's escapes.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, BenchError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut config = Config::default();
+        config.apply_file(&text)?;
+        Ok(config)
+    }
+
+    /// Writes every setting in the format `from_reader` reads back.
+    pub fn to_toml(&self) -> String {
+        let names: Vec<_> = self
+            .implementations
+            .iter()
+            .map(|implementation| format!("\"{}\"", implementation.name()))
+            .collect();
+        let mut text = String::new();
+        let _ = writeln!(text, "num_entities = {}", self.num_entities);
+        let _ = writeln!(text, "num_frames = {}", self.num_frames);
+        let _ = writeln!(text, "operations_per_frame = {}", self.operations_per_frame);
+        let _ = writeln!(text, "implementations = [{}]", names.join(", "));
+        let _ = writeln!(text, "compare_allocators = {}", self.compare_allocators);
+        let _ = writeln!(text, "heavy_compute = {}", self.heavy_compute);
+        let _ = writeln!(
+            text,
+            "initial_clones_per_entity = {}",
+            self.initial_clones_per_entity
+        );
+        let _ = writeln!(text, "retain_rate = {}", self.retain_rate);
+        let _ = writeln!(text, "quiet = {}", self.quiet);
+        let _ = writeln!(text, "micro = {}", self.micro);
+        let _ = writeln!(text, "handoff = {}", self.handoff);
+        let _ = writeln!(text, "clone_tax = {}", self.clone_tax);
+        let _ = writeln!(text, "drop_order = {}", self.drop_order);
+        let _ = writeln!(text, "clone_drop_split = {}", self.clone_drop_split);
+        let _ = writeln!(text, "weak_game = {}", self.weak_game);
+        if let Some(count) = self.archetypes {
+            let _ = writeln!(text, "archetypes = {}", count);
+        }
+        let _ = writeln!(text, "max_id_gap = {}", self.max_id_gap);
+        let _ = writeln!(text, "seed = {}", self.seed);
+        if let Some(steps) = self.fuzz_steps {
+            let _ = writeln!(text, "fuzz_steps = {}", steps);
+        }
+        if let Some(steps) = self.stress_steps {
+            let _ = writeln!(text, "stress_steps = {}", steps);
+        }
+        if let Some(threads) = self.threads {
+            let _ = writeln!(text, "threads = {}", threads);
+        }
+        let _ = writeln!(text, "pin_threads = {}", self.pin_threads);
+        if let Some(threads) = self.stress_arc {
+            let _ = writeln!(text, "stress_arc = {}", threads);
+        }
+        let _ = writeln!(text, "stress_arc_race = {}", self.stress_arc_race);
+        let _ = writeln!(text, "flush_cache = {}", self.flush_cache);
+        let _ = writeln!(text, "cache_report = {}", self.cache_report);
+        let _ = writeln!(text, "alloc_histogram = {}", self.alloc_histogram);
+        let _ = writeln!(text, "dependent_access = {}", self.dependent_access);
+        let _ = writeln!(text, "churn_per_frame = {}", self.churn_per_frame);
+        let _ = writeln!(text, "prewarm_blocks = {}", self.prewarm_blocks);
+        let counts: Vec<_> = self
+            .sweep_entities
+            .iter()
+            .map(|count| count.to_string())
+            .collect();
+        let _ = writeln!(text, "sweep_entities = [{}]", counts.join(", "));
+        let _ = writeln!(text, "samples = {}", self.samples);
+        let _ = writeln!(text, "format = \"{}\"", self.format.name());
+        if let Some(timeout) = self.timeout {
+            let _ = writeln!(text, "timeout = {}", timeout.as_secs_f64());
+        }
+        if let Some(skew) = self.zipf {
+            let _ = writeln!(text, "zipf = {}", skew);
+        }
+        if let Some(fraction) = self.mut_or_read {
+            let _ = writeln!(text, "mut_or_read = {}", fraction);
+        }
+        if let Some(fraction) = self.upgrade_storm {
+            let _ = writeln!(text, "upgrade_storm = {}", fraction);
+        }
+        if let Some(path) = &self.trace {
+            let _ = writeln!(text, "trace = {}", toml_escape(path));
+        }
+        text
+    }
+
+    /// Applies the settings of a config file on top of `self`.
+    fn apply_file(&mut self, text: &str) -> Result<(), BenchError> {
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |details: String| {
+                BenchError::InvalidConfig(format!("line {}: {}", index + 1, details))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected `key = value`, got `{}`", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            let bad_value = || invalid(format!("invalid value `{}` for `{}`", value, key));
+            match key {
+                "num_entities" => {
+                    self.num_entities = toml_count(value).ok_or(BenchError::InvalidEntities)?
+                }
+                "num_frames" => {
+                    self.num_frames = toml_count(value).ok_or(BenchError::InvalidFrames)?
+                }
+                "operations_per_frame" => {
+                    self.operations_per_frame = toml_value(value).ok_or_else(bad_value)?
+                }
+                "implementations" => {
+                    let names = toml_array(value)
+                        .ok_or_else(bad_value)?
+                        .into_iter()
+                        .map(|name| toml_string(name).ok_or_else(bad_value))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    self.implementations =
+                        Implementation::from_names(names.iter().map(String::as_str))?;
+                }
+                "compare_allocators" => {
+                    self.compare_allocators = toml_value(value).ok_or_else(bad_value)?
+                }
+                "heavy_compute" => self.heavy_compute = toml_value(value).ok_or_else(bad_value)?,
+                "initial_clones_per_entity" => {
+                    self.initial_clones_per_entity = toml_value(value).ok_or_else(bad_value)?
+                }
+                "retain_rate" => {
+                    self.retain_rate =
+                        parse_fraction(Some(value.to_string())).ok_or_else(bad_value)?
+                }
+                "quiet" => self.quiet = toml_value(value).ok_or_else(bad_value)?,
+                "micro" => self.micro = toml_value(value).ok_or_else(bad_value)?,
+                "handoff" => self.handoff = toml_value(value).ok_or_else(bad_value)?,
+                "clone_tax" => self.clone_tax = toml_value(value).ok_or_else(bad_value)?,
+                "drop_order" => self.drop_order = toml_value(value).ok_or_else(bad_value)?,
+                "clone_drop_split" => {
+                    self.clone_drop_split = toml_value(value).ok_or_else(bad_value)?
+                }
+                "weak_game" => self.weak_game = toml_value(value).ok_or_else(bad_value)?,
+                "archetypes" => self.archetypes = Some(toml_count(value).ok_or_else(bad_value)?),
+                "max_id_gap" => self.max_id_gap = toml_value(value).ok_or_else(bad_value)?,
+                "seed" => self.seed = toml_value(value).ok_or_else(bad_value)?,
+                "fuzz_steps" => self.fuzz_steps = Some(toml_count(value).ok_or_else(bad_value)?),
+                "stress_steps" => {
+                    self.stress_steps = Some(toml_count(value).ok_or_else(bad_value)?)
+                }
+                "threads" => self.threads = Some(toml_count(value).ok_or_else(bad_value)?),
+                "pin_threads" => self.pin_threads = toml_value(value).ok_or_else(bad_value)?,
+                "stress_arc" => self.stress_arc = Some(toml_count(value).ok_or_else(bad_value)?),
+                "stress_arc_race" => {
+                    self.stress_arc_race = toml_value(value).ok_or_else(bad_value)?
+                }
+                "flush_cache" => self.flush_cache = toml_value(value).ok_or_else(bad_value)?,
+                "cache_report" => self.cache_report = toml_value(value).ok_or_else(bad_value)?,
+                "dependent_access" => {
+                    self.dependent_access = toml_value(value).ok_or_else(bad_value)?
+                }
+                "sweep_entities" => {
+                    self.sweep_entities = toml_array(value)
+                        .ok_or_else(bad_value)?
+                        .into_iter()
+                        .map(toml_count)
+                        .collect::<Option<_>>()
+                        .ok_or(BenchError::InvalidEntities)?
+                }
+                "samples" => self.samples = toml_count(value).ok_or_else(bad_value)?,
+                "format" => {
+                    self.format = toml_string(value)
+                        .and_then(|name| OutputFormat::from_name(&name))
+                        .ok_or_else(bad_value)?
+                }
+                "prewarm_blocks" => {
+                    self.prewarm_blocks = toml_value(value).ok_or_else(bad_value)?
+                }
+                "churn_per_frame" => {
+                    self.churn_per_frame = toml_value(value).ok_or_else(bad_value)?
+                }
+                "timeout" => {
+                    self.timeout =
+                        Some(parse_timeout(Some(value.to_string())).ok_or_else(bad_value)?)
+                }
+                "zipf" => {
+                    self.zipf = Some(parse_skew(Some(value.to_string())).ok_or_else(bad_value)?)
+                }
+                "mut_or_read" => {
+                    self.mut_or_read =
+                        Some(parse_fraction(Some(value.to_string())).ok_or_else(bad_value)?)
+                }
+                "trace" => self.trace = Some(toml_string(value).ok_or_else(bad_value)?),
+                "upgrade_storm" => {
+                    self.upgrade_storm =
+                        Some(parse_fraction(Some(value.to_string())).ok_or_else(bad_value)?)
+                }
+                "alloc_histogram" => {
+                    self.alloc_histogram = toml_value(value).ok_or_else(bad_value)?
+                }
+                _ => return Err(invalid(format!("unknown key `{}`", key))),
+            }
+        }
+        self.validate()
+    }
+}
+
+/// Parses a TOML integer (`_` separators allowed) or boolean.
+fn toml_value<T: FromStr>(value: &str) -> Option<T> {
+    value.replace('_', "").parse().ok()
+}
+
+/// Parses a strictly positive TOML integer.
+fn toml_count(value: &str) -> Option<usize> {
+    toml_value(value).filter(|&count| count > 0)
+}
+
+/// The elements of a single-line TOML array, trailing comma allowed.
+fn toml_array(value: &str) -> Option<Vec<&str>> {
+    let elements = value.strip_prefix('[')?.strip_suffix(']')?;
+    Some(
+        elements
+            .split(',')
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .collect(),
+    )
+}
+
+/// `line` up to the `#` starting its comment, if any: the first one outside
+/// a basic string.
+fn strip_comment(line: &str) -> &str {
+    let (mut in_string, mut escaped) = (false, false);
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The contents of a basic TOML string, with its escapes resolved.
+fn toml_string(value: &str) -> Option<String> {
+    let contents = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::with_capacity(contents.len());
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '"' => return None,
+            '\\' => match chars.next()? {
+                'b' => '\u{8}',
+                't' => '\t',
+                'n' => '\n',
+                'f' => '\u{c}',
+                'r' => '\r',
+                escaped @ ('"' | '\\') => escaped,
+                'u' => unicode_escape(&mut chars, 4)?,
+                'U' => unicode_escape(&mut chars, 8)?,
+                _ => return None,
+            },
+            c => c,
+        };
+        unescaped.push(c);
+    }
+    Some(unescaped)
+}
+
+/// The character named by the next `digits` hex digits of `chars`.
+fn unicode_escape(chars: &mut std::str::Chars<'_>, digits: usize) -> Option<char> {
+    let code: String = chars.take(digits).collect();
+    if code.len() != digits {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(&code, 16).ok()?)
+}
+
+/// `value` as a basic TOML string, quoted and escaped so that `toml_string`
+/// reads it back unchanged.
+fn toml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04X}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parses the value of `arg`, failing with `InvalidArgument` when it's
+/// missing or malformed.
+fn parse_value<T: FromStr>(value: Option<String>, arg: String) -> Result<T, BenchError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or(BenchError::InvalidArgument(arg))
+}
+
+/// Parses a strictly positive number of seconds.
+pub(crate) fn parse_timeout(value: Option<String>) -> Option<Duration> {
+    let seconds: f64 = value?.parse().ok()?;
+    (seconds > 0.0)
+        .then(|| Duration::try_from_secs_f64(seconds).ok())
+        .flatten()
+}
+
+/// Parses a strictly positive, finite Zipf exponent.
+fn parse_skew(value: Option<String>) -> Option<f64> {
+    let skew: f64 = value?.parse().ok()?;
+    (skew > 0.0 && skew.is_finite()).then_some(skew)
+}
+
+/// Parses a fraction in `0..=1`.
+fn parse_fraction(value: Option<String>) -> Option<f64> {
+    let fraction: f64 = value?.parse().ok()?;
+    (0.0..=1.0).contains(&fraction).then_some(fraction)
+}
+
+/// Parses a strictly positive count.
+fn parse_count(value: Option<String>) -> Option<usize> {
+    value?.parse().ok().filter(|&count| count > 0)
+}
+
+/// `frames * operations_per_frame * entities`, or `Overflow` if that doesn't
+/// fit in a `usize`.
+pub fn total_operations(
+    frames: usize,
+    operations_per_frame: usize,
+    entities: usize,
+) -> Result<usize, BenchError> {
+    frames
+        .checked_mul(operations_per_frame)
+        .and_then(|operations| operations.checked_mul(entities))
+        .ok_or(BenchError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn total_work_near_usize_max_overflows_cleanly() {
+        assert_eq!(
+            total_operations(usize::MAX, 2, 1),
+            Err(BenchError::Overflow)
+        );
+        assert_eq!(
+            total_operations(1, usize::MAX / 2, 3),
+            Err(BenchError::Overflow)
+        );
+        assert_eq!(total_operations(usize::MAX, 1, 1), Ok(usize::MAX));
+        let huge = usize::MAX / 2;
+        assert_eq!(
+            Config::from_args(args(&format!("--frames {} --ops 4 --entities 1", huge))).err(),
+            Some(BenchError::Overflow)
+        );
+        assert_eq!(
+            Config::from_args(args(&format!("--entities 1 --sweep-entities 1,{}", huge))).err(),
+            Some(BenchError::Overflow)
+        );
+    }
+
+    #[test]
+    fn to_toml_reads_back_as_the_same_config() {
+        let default = Config::default();
+        assert_eq!(
+            Config::from_reader(default.to_toml().as_bytes()).unwrap(),
+            default
+        );
+
+        // The sweep and the archetypes are two modes, so they can't share a run.
+        for line in [
+            "--entities 64 --frames 3 --ops 2 --impl customrc,StdArc,ArenaGen \
+             --initial-clones 2 --retain-rate 0.25 --quiet --seed 9 --churn 4 \
+             --id-gaps 3 --zipf 1.5 --timeout 2.5 --sweep-entities 8,16 --samples 3 \
+             --format markdown --prewarm-allocator 10",
+            "--entities 64 --archetypes 4",
+        ] {
+            let config = Config::from_args(args(line)).unwrap();
+            assert_eq!(
+                Config::from_reader(config.to_toml().as_bytes()).unwrap(),
+                config
+            );
+        }
+    }
+
+    #[test]
+    fn trace_paths_are_escaped_both_ways() {
+        let mut config = Config::default();
+        for path in [r#"C:\traces\"quoted" #1.json"#, "tab\tand\u{1}control"] {
+            config.trace = Some(path.to_string());
+            let text = config.to_toml();
+            assert_eq!(Config::from_reader(text.as_bytes()).unwrap(), config);
+        }
+        let read = |line: &str| Config::from_reader(line.as_bytes()).map(|config| config.trace);
+        assert_eq!(
+            read(r#"trace = "a\u00e9\U0001F600" # a comment"#),
+            Ok(Some("a\u{e9}\u{1F600}".to_string()))
+        );
+        assert!(read(r#"trace = "unescaped " quote""#).is_err());
+        assert!(read(r#"trace = "bad \q escape""#).is_err());
+        assert!(read(r#"trace = "short \u12""#).is_err());
+    }
+
+    #[test]
+    fn only_std_rc_and_custom_rc_run_unless_all_are_asked_for() {
+        assert_eq!(
+            Config::default().implementations,
+            [Implementation::StdRc, Implementation::CustomRc]
+        );
+        let all = Config::from_args(args("--impl all")).unwrap();
+        assert_eq!(all.implementations, Implementation::ALL);
+        let config = Config::from_args(args("--impl StdArc,ALL")).unwrap();
+        assert_eq!(config.implementations.len(), 1 + Implementation::ALL.len());
+        assert_eq!(
+            Config::from_args(args("--impl alll")).err(),
+            Some(BenchError::UnknownImpl("alll".to_string()))
+        );
+        let file = Config::from_reader(&b"implementations = [\"All\"]\n"[..]).unwrap();
+        assert_eq!(file.implementations, Implementation::ALL);
+    }
+
+    #[test]
+    fn two_modes_in_one_run_are_refused() {
+        assert_eq!(
+            Config::from_args(args("--micro --clone-tax")).err(),
+            Some(BenchError::InvalidArgument(
+                "--clone-tax (conflicts with --micro)".to_string()
+            ))
+        );
+        for line in [
+            "--threads 2 --stress-arc 2",
+            "--cache-report --format markdown",
+            "--sweep-entities 1,2 --weak-game",
+            "--fuzz-equivalence 10 --stress-scopes 10",
+        ] {
+            assert!(
+                matches!(
+                    Config::from_args(args(line)),
+                    Err(BenchError::InvalidArgument(_))
+                ),
+                "{}",
+                line
+            );
+        }
+        let file = Config::from_reader(&b"micro = true\nhandoff = true\n"[..]);
+        assert!(matches!(file, Err(BenchError::InvalidArgument(_))));
+        // A sweep printed as markdown is still one mode.
+        assert!(Config::from_args(args("--sweep-entities 1,2 --format markdown")).is_ok());
+    }
+}
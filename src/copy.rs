@@ -0,0 +1,43 @@
+use std::ops::Deref;
+
+use crate::entity::Entity;
+use crate::rc::{Constructor, CountedRc};
+
+// ========================
+// Implement CopyRc
+// ========================
+
+/// The other end of the spectrum from `CustomRc`: a handle that owns its
+/// own allocation, so cloning it allocates and deep-copies the value
+/// instead of sharing it. Nothing is ever shared, so there is no count and
+/// nothing to get wrong about one.
+pub struct CopyRc<T: Clone>(Box<T>);
+
+impl<T: Clone> Clone for CopyRc<T> {
+    fn clone(&self) -> Self {
+        CopyRc(Box::new(T::clone(&self.0)))
+    }
+}
+
+impl<T: Clone> Deref for CopyRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Clone> Constructor<T> for CopyRc<T> {
+    fn new(value: T) -> Self {
+        CopyRc(Box::new(value))
+    }
+}
+
+impl CountedRc<Entity> for CopyRc<Entity> {
+    const SHARED: bool = false;
+
+    /// Always 1: every handle is the only one to its copy.
+    fn strong_count(&self) -> usize {
+        1
+    }
+}
@@ -0,0 +1,105 @@
+// ========================
+// Define the Entity
+// ========================
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "ffi", repr(C))]
+pub struct Entity {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Entity {
+    pub fn update(&mut self) {
+        // Simple update: move the entity
+        self.x += 1.0;
+        self.y += 1.0;
+    }
+}
+
+/// An `Entity` that owns a heap-allocated name, so dropping it runs a real
+/// destructor that frees a second allocation. `Entity` itself has nothing
+/// to drop: freeing one is only the `dealloc` of its handle's block.
+#[derive(Debug, Clone)]
+pub struct NamedEntity {
+    pub entity: Entity,
+    pub name: String,
+}
+
+impl NamedEntity {
+    pub fn new(entity: Entity) -> Self {
+        NamedEntity {
+            entity,
+            name: format!("entity {}", entity.id),
+        }
+    }
+}
+
+// ========================
+// Heavy Batched Update
+// ========================
+
+/// Integration steps per entity in `Entity::update_batch`.
+const BATCH_STEPS: usize = 16;
+
+#[cfg(feature = "simd")]
+const LANES: usize = 8;
+
+/// One integration step of the batched update for a single entity.
+#[inline(always)]
+fn step(x: f32, y: f32) -> (f32, f32) {
+    let x = x * 0.99 + y * 0.01 + 1.0;
+    let y = y * 0.99 - x * 0.01 + 1.0;
+    (x, y)
+}
+
+impl Entity {
+    /// A heavier, vectorizable update over positions gathered into parallel
+    /// slices: `BATCH_STEPS` steps of a small rotation-and-drift integrator
+    /// per entity. Returns the sum of the results so it can't be optimized out.
+    pub fn update_batch(xs: &mut [f32], ys: &mut [f32]) -> f32 {
+        #[cfg(feature = "simd")]
+        return update_batch_simd(xs, ys);
+        #[cfg(not(feature = "simd"))]
+        return update_batch_scalar(xs, ys);
+    }
+}
+
+#[cfg(feature = "simd")]
+fn update_batch_simd(xs: &mut [f32], ys: &mut [f32]) -> f32 {
+    use std::simd::num::SimdFloat;
+    use std::simd::Simd;
+
+    let split = xs.len().min(ys.len()) / LANES * LANES;
+    let (xs, x_rest) = xs.split_at_mut(split);
+    let (ys, y_rest) = ys.split_at_mut(split);
+    let mut sum = Simd::<f32, LANES>::splat(0.0);
+    for (x_chunk, y_chunk) in xs.chunks_exact_mut(LANES).zip(ys.chunks_exact_mut(LANES)) {
+        let mut x = Simd::<f32, LANES>::from_slice(x_chunk);
+        let mut y = Simd::<f32, LANES>::from_slice(y_chunk);
+        for _ in 0..BATCH_STEPS {
+            // Same arithmetic as `step`, eight entities at a time.
+            x = x * Simd::splat(0.99) + y * Simd::splat(0.01) + Simd::splat(1.0);
+            y = y * Simd::splat(0.99) - x * Simd::splat(0.01) + Simd::splat(1.0);
+        }
+        x.copy_to_slice(x_chunk);
+        y.copy_to_slice(y_chunk);
+        sum += x + y;
+    }
+    sum.reduce_sum() + update_batch_scalar(x_rest, y_rest)
+}
+
+fn update_batch_scalar(xs: &mut [f32], ys: &mut [f32]) -> f32 {
+    let mut sum = 0.0;
+    for (x, y) in xs.iter_mut().zip(ys.iter_mut()) {
+        let (mut px, mut py) = (*x, *y);
+        for _ in 0..BATCH_STEPS {
+            (px, py) = step(px, py);
+        }
+        *x = px;
+        *y = py;
+        sum += px + py;
+    }
+    sum
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::entity::Entity;
+use crate::error::BenchError;
+use crate::rc::{CountedRc, RcLike};
+use crate::rng::SplitMix64;
+
+// ========================
+// Record Operations
+// ========================
+
+/// One step of the equivalence harness. Handles are addressed by their slot
+/// in the harness's handle list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Push a new handle to a fresh entity with this id.
+    Construct(usize),
+    /// Push a clone of the handle in this slot.
+    Clone(usize),
+    /// Drop the handle in this slot (the last handle moves into it).
+    Drop(usize),
+    /// Read the entity behind the handle in this slot.
+    Deref(usize),
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Construct(id) => write!(f, "construct {}", id),
+            Op::Clone(slot) => write!(f, "clone {}", slot),
+            Op::Drop(slot) => write!(f, "drop {}", slot),
+            Op::Deref(slot) => write!(f, "deref {}", slot),
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("malformed op `{}`", line))?;
+        let arg = arg
+            .trim()
+            .parse()
+            .map_err(|_| format!("malformed op `{}`", line))?;
+        match name {
+            "construct" => Ok(Op::Construct(arg)),
+            "clone" => Ok(Op::Clone(arg)),
+            "drop" => Ok(Op::Drop(arg)),
+            "deref" => Ok(Op::Deref(arg)),
+            _ => Err(format!("unknown op `{}`", name)),
+        }
+    }
+}
+
+/// The exact sequence of operations a harness run performed, one per line
+/// when saved, so a divergence can be replayed and checked in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpLog {
+    ops: Vec<Op>,
+}
+
+impl OpLog {
+    pub fn new() -> Self {
+        OpLog::default()
+    }
+
+    pub fn record(&mut self, op: Op) {
+        self.ops.push(op);
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Re-runs the log against a fresh `RcType` and returns where it ended up.
+    pub fn replay<RcType>(&self) -> ReplayState
+    where
+        RcType: RcLike<Entity>,
+    {
+        let mut replayer = Replayer::<RcType>::new();
+        for &op in &self.ops {
+            replayer.apply(op);
+        }
+        replayer.state()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut text = String::new();
+        for op in &self.ops {
+            text.push_str(&op.to_string());
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let ops = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.parse()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(OpLog { ops })
+    }
+}
+
+// ========================
+// Replay Operations
+// ========================
+
+/// What an implementation observed: every id read by a `Deref`, and the ids
+/// behind the handles still alive at the end.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayState {
+    pub reads: Vec<usize>,
+    pub live: Vec<usize>,
+}
+
+struct Replayer<RcType> {
+    handles: Vec<RcType>,
+    reads: Vec<usize>,
+}
+
+impl<RcType> Replayer<RcType>
+where
+    RcType: RcLike<Entity>,
+{
+    fn new() -> Self {
+        Replayer {
+            handles: Vec::new(),
+            reads: Vec::new(),
+        }
+    }
+
+    /// Applies `op`; out-of-range slots are ignored so hand-edited logs can't panic.
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Construct(id) => {
+                let entity = Entity {
+                    id,
+                    x: id as f32,
+                    y: -(id as f32),
+                };
+                self.handles.push(RcType::new(entity));
+            }
+            Op::Clone(slot) => {
+                if let Some(handle) = self.handles.get(slot) {
+                    let clone = handle.clone();
+                    self.handles.push(clone);
+                }
+            }
+            Op::Drop(slot) => {
+                if slot < self.handles.len() {
+                    self.handles.swap_remove(slot);
+                }
+            }
+            Op::Deref(slot) => {
+                if let Some(handle) = self.handles.get(slot) {
+                    self.reads.push(handle.id);
+                }
+            }
+        }
+    }
+
+    fn state(&self) -> ReplayState {
+        ReplayState {
+            reads: self.reads.clone(),
+            live: self.handles.iter().map(|handle| handle.id).collect(),
+        }
+    }
+}
+
+impl<RcType> Replayer<RcType>
+where
+    RcType: CountedRc<Entity>,
+{
+    fn strong_counts(&self) -> Vec<usize> {
+        self.handles.iter().map(CountedRc::strong_count).collect()
+    }
+}
+
+// ========================
+// Fuzz Two Implementations
+// ========================
+
+/// Drives `A` and `B` through the same `steps` random operations and compares
+/// what they observe, including every handle's strong count, after each one.
+/// On a mismatch, returns the log up to and including the diverging operation.
+pub fn fuzz_equivalence<A, B>(seed: u64, steps: usize) -> Result<OpLog, OpLog>
+where
+    A: CountedRc<Entity>,
+    B: CountedRc<Entity>,
+{
+    let mut rng = SplitMix64::new(seed);
+    let mut log = OpLog::new();
+    let mut a = Replayer::<A>::new();
+    let mut b = Replayer::<B>::new();
+    let mut next_id = 0;
+    for _ in 0..steps {
+        let len = a.handles.len();
+        let op = match rng.below(4) {
+            _ if len == 0 => Op::Construct(next_id),
+            0 => Op::Construct(next_id),
+            1 => Op::Clone(rng.below(len)),
+            2 => Op::Drop(rng.below(len)),
+            _ => Op::Deref(rng.below(len)),
+        };
+        if let Op::Construct(_) = op {
+            next_id += 1;
+        }
+        log.record(op);
+        a.apply(op);
+        b.apply(op);
+        if a.reads.last() != b.reads.last()
+            || a.state().live != b.state().live
+            || a.strong_counts() != b.strong_counts()
+        {
+            return Err(log);
+        }
+    }
+    Ok(log)
+}
+
+// ========================
+// Churn Clones Across Scopes
+// ========================
+
+/// Entities `stress_scopes` clones from.
+const STRESS_ENTITIES: usize = 16;
+
+/// Deepest nesting of scopes `stress_scopes` reaches.
+const MAX_STRESS_DEPTH: usize = 8;
+
+/// Clones random entities into temporaries `steps` times, in scopes nested
+/// to a random depth, and drops them early, at the end of their scope or
+/// while unwinding the nesting. Every clone is read through, and once all
+/// of them are gone every original must be back to a strong count of 1.
+/// Returns the number of clones taken.
+///
+/// It's the realistic churn pattern rather than single calls, bounded and
+/// deterministic for a seed, so that a few hundred steps finish under Miri
+/// (`cargo +nightly miri run -- --stress-scopes 300`).
+pub fn stress_scopes<RcType>(seed: u64, steps: usize) -> Result<usize, BenchError>
+where
+    RcType: CountedRc<Entity>,
+{
+    let mut rng = SplitMix64::new(seed);
+    let handles: Vec<RcType> = (0..STRESS_ENTITIES)
+        .map(|id| {
+            RcType::new(Entity {
+                id,
+                x: id as f32,
+                y: 0.0,
+            })
+        })
+        .collect();
+    let mut remaining = steps;
+    let mut clones = 0;
+    while remaining > 0 {
+        clones += descend(&handles, &mut rng, MAX_STRESS_DEPTH, &mut remaining)?;
+    }
+    match handles
+        .iter()
+        .find(|entity_rc| entity_rc.strong_count() != 1)
+    {
+        Some(entity_rc) => Err(BenchError::CrossCheckFailed(format!(
+            "entity {} kept strong count {} after its clones were dropped",
+            entity_rc.id,
+            entity_rc.strong_count()
+        ))),
+        None => Ok(clones),
+    }
+}
+
+/// One scope of `stress_scopes`: a few temporaries, maybe a nested scope,
+/// then one temporary dropped early and the rest with the scope.
+fn descend<RcType>(
+    handles: &[RcType],
+    rng: &mut SplitMix64,
+    depth: usize,
+    remaining: &mut usize,
+) -> Result<usize, BenchError>
+where
+    RcType: CountedRc<Entity>,
+{
+    let mut temporaries: Vec<RcType> = Vec::new();
+    let mut clones = 0;
+    for _ in 0..rng.below(4) + 1 {
+        if *remaining == 0 {
+            break;
+        }
+        *remaining -= 1;
+        clones += 1;
+        let index = rng.below(handles.len());
+        let cloned_rc = match rng.below(3) {
+            // A clone of a clone, not of the original.
+            0 if !temporaries.is_empty() => {
+                let temporary = &temporaries[rng.below(temporaries.len())];
+                RcType::clone(temporary)
+            }
+            _ => handles[index].clone(),
+        };
+        if cloned_rc.x != cloned_rc.id as f32 {
+            return Err(BenchError::CrossCheckFailed(format!(
+                "a clone of entity {} read x = {}",
+                cloned_rc.id, cloned_rc.x
+            )));
+        }
+        match rng.below(3) {
+            0 => drop(cloned_rc),
+            _ => temporaries.push(cloned_rc),
+        }
+    }
+    if depth > 0 && *remaining > 0 && rng.below(2) == 0 {
+        clones += descend(handles, rng, depth - 1, remaining)?;
+    }
+    if !temporaries.is_empty() {
+        drop(temporaries.swap_remove(rng.below(temporaries.len())));
+    }
+    Ok(clones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc::{CustomRc, StdRcWrapper};
+
+    fn short_log() -> OpLog {
+        let mut log = OpLog::new();
+        for op in [
+            Op::Construct(7),
+            Op::Clone(0),
+            Op::Construct(8),
+            Op::Deref(1),
+            Op::Drop(0),
+            Op::Deref(0),
+        ] {
+            log.record(op);
+        }
+        log
+    }
+
+    #[test]
+    fn replaying_a_log_reaches_the_same_state() {
+        let log = short_log();
+        let expected = ReplayState {
+            reads: vec![7, 8],
+            // Dropping slot 0 moved the last handle, entity 8, into it.
+            live: vec![8, 7],
+        };
+        assert_eq!(log.replay::<CustomRc<Entity>>(), expected);
+        assert_eq!(log.replay::<StdRcWrapper<Entity>>(), expected);
+    }
+
+    #[test]
+    fn a_saved_log_loads_back_unchanged() {
+        let log = short_log();
+        let path = std::env::temp_dir().join(format!("rc-playground-oplog-{}", std::process::id()));
+        log.save(&path).unwrap();
+        let loaded = OpLog::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), log);
+    }
+
+    #[test]
+    fn malformed_ops_are_rejected() {
+        assert_eq!("clone 3".parse(), Ok(Op::Clone(3)));
+        assert!("clone".parse::<Op>().is_err());
+        assert!("clone x".parse::<Op>().is_err());
+        assert!("swap 1".parse::<Op>().is_err());
+    }
+
+    #[test]
+    fn a_fuzzed_log_replays_to_the_same_state_twice() {
+        let log = fuzz_equivalence::<StdRcWrapper<Entity>, CustomRc<Entity>>(5, 200).unwrap();
+        assert_eq!(log.ops().len(), 200);
+        assert_eq!(
+            log.replay::<CustomRc<Entity>>(),
+            log.replay::<StdRcWrapper<Entity>>()
+        );
+    }
+
+    #[test]
+    fn scoped_churn_is_deterministic_and_balances() {
+        let clones = stress_scopes::<CustomRc<Entity>>(11, 300).unwrap();
+        assert!(clones >= 300);
+        assert_eq!(stress_scopes::<CustomRc<Entity>>(11, 300), Ok(clones));
+        assert_eq!(stress_scopes::<StdRcWrapper<Entity>>(11, 300), Ok(clones));
+        assert_eq!(stress_scopes::<CustomRc<Entity>>(11, 0), Ok(0));
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+/// Everything that can go wrong while configuring or running a benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BenchError {
+    /// The frame count was zero or not a number.
+    InvalidFrames,
+    /// The entity count was zero or not a number.
+    InvalidEntities,
+    /// An implementation name that isn't one of `Implementation::ALL`.
+    UnknownImpl(String),
+    /// The allocator returned null.
+    AllocFailed,
+    /// The configured amount of work doesn't fit in a `usize`.
+    Overflow,
+    /// A command-line argument that isn't recognised or is missing its value.
+    InvalidArgument(String),
+    /// Two implementations disagreed; the operation log was saved to this path.
+    Diverged(String),
+    /// Two implementations ended a cross-check in different states.
+    CrossCheckFailed(String),
+    /// Reading or writing a file failed.
+    Io(String),
+    /// A config file line that couldn't be understood.
+    InvalidConfig(String),
+    /// A game ended with handles that were cloned but never dropped, or
+    /// dropped once too often.
+    Unbalanced(String),
+}
+
+impl fmt::Display for BenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BenchError::InvalidFrames => write!(f, "frame count must be a positive integer"),
+            BenchError::InvalidEntities => write!(f, "entity count must be a positive integer"),
+            BenchError::UnknownImpl(name) => write!(f, "unknown implementation `{}`", name),
+            BenchError::AllocFailed => write!(f, "allocation failed"),
+            BenchError::Overflow => write!(f, "configured workload overflows usize"),
+            BenchError::InvalidArgument(arg) => write!(f, "invalid argument `{}`", arg),
+            BenchError::Diverged(path) => {
+                write!(
+                    f,
+                    "implementations diverged, operation log saved to {}",
+                    path
+                )
+            }
+            BenchError::CrossCheckFailed(details) => write!(f, "cross-check failed: {}", details),
+            BenchError::Io(err) => write!(f, "I/O error: {}", err),
+            BenchError::InvalidConfig(details) => write!(f, "invalid config file: {}", details),
+            BenchError::Unbalanced(details) => write!(f, "balance check failed: {}", details),
+        }
+    }
+}
+
+impl std::error::Error for BenchError {}
+
+/// A fallible allocation couldn't be satisfied (or its size overflowed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory allocation failed")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+impl From<AllocError> for BenchError {
+    fn from(_: AllocError) -> Self {
+        BenchError::AllocFailed
+    }
+}
+
+impl From<std::io::Error> for BenchError {
+    fn from(err: std::io::Error) -> Self {
+        BenchError::Io(err.to_string())
+    }
+}
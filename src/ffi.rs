@@ -0,0 +1,74 @@
+//! A C interface to `CustomRc<Entity>`, only compiled in with the `ffi`
+//! feature, which also makes `Entity` itself `#[repr(C)]`.
+//!
+//! C code sees a handle as a plain `const Entity *`, from
+//! `CustomRc::into_raw`. The allocation behind it is, in order,
+//!
+//! ```c
+//! struct {
+//!     size_t strong;  /* CustomRc handles */
+//!     size_t weak;    /* CustomWeak handles, plus one for all strong ones */
+//!     Entity entity;  /* at ENTITY_OFFSET, where the handle points */
+//! };
+//! ```
+//!
+//! but the counts are for the Rust side to manage: C should only read the
+//! entity, and share or release it with the functions below.
+
+use std::mem::ManuallyDrop;
+
+use crate::entity::Entity;
+use crate::rc::{value_offset, CustomRc};
+
+/// Bytes from the start of a `CustomRc<Entity>` allocation to the entity,
+/// i.e. how far `CustomRc::into_raw` points past the counts.
+pub const ENTITY_OFFSET: usize = value_offset::<Entity>();
+
+/// Adds a strong handle to `entity` and returns it (the same pointer).
+///
+/// # Safety
+/// `entity` must come from `CustomRc::<Entity>::into_raw` (or this function)
+/// and not have been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rc_playground_entity_clone(entity: *const Entity) -> *const Entity {
+    let rc = ManuallyDrop::new(CustomRc::from_raw(entity));
+    CustomRc::into_raw(CustomRc::clone(&rc))
+}
+
+/// Releases one strong handle to `entity`, dropping it if that was the last.
+///
+/// # Safety
+/// As for `rc_playground_entity_clone`; `entity` must not be used again
+/// unless other handles to it remain.
+#[no_mangle]
+pub unsafe extern "C" fn rc_playground_entity_drop(entity: *const Entity) {
+    drop(CustomRc::from_raw(entity));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_cross_the_c_interface_and_come_back() {
+        let rc = CustomRc::new(Entity {
+            id: 4,
+            x: 1.0,
+            y: 2.0,
+        });
+        let weak = CustomRc::downgrade(&rc);
+        let raw = CustomRc::into_raw(rc);
+        // Right after the two counts, since `Entity` is no more aligned.
+        assert_eq!(ENTITY_OFFSET, 2 * std::mem::size_of::<usize>());
+        unsafe {
+            let shared = rc_playground_entity_clone(raw);
+            assert_eq!(shared, raw);
+            assert_eq!(weak.strong_count(), 2);
+            assert_eq!((*shared).id, 4);
+            rc_playground_entity_drop(shared);
+            assert_eq!(weak.strong_count(), 1);
+            rc_playground_entity_drop(raw);
+        }
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,1759 @@
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::time::Duration;
+
+use crate::cell::SharedEntity;
+use crate::clock::{Clock, SystemClock};
+use crate::config::{total_operations, Config};
+use crate::entity::Entity;
+use crate::error::BenchError;
+use crate::rc::{Constructor, CountedRc, MutableRc, WeakRc};
+use crate::rng::SplitMix64;
+
+// ========================
+// Define the Game Structure
+// ========================
+
+pub struct Game<RcType>
+where
+    RcType: CountedRc<Entity>,
+{
+    entities: Vec<RcType>,
+    // Prefix of the progress lines, usually the implementation's name.
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    heavy_compute: bool,
+    initial_clones_per_entity: usize,
+    // Share of the set-up entities given one more clone in `retained`.
+    retain_rate: f64,
+    // How long `run` may take before it stops after the current frame.
+    timeout: Option<Duration>,
+    // Entities despawned and respawned at the end of every frame.
+    churn_per_frame: usize,
+    // Entity list position of the next despawn, and the ids of every spawn.
+    next_despawn: usize,
+    ids: EntityIds,
+    // The first id churn spawned rather than setup.
+    first_respawn: usize,
+    // Ids of the set-up entities `retain_rate` picked, sorted.
+    picked: Vec<usize>,
+    // Extra clones made by `setup`, held for the whole game so every entity
+    // starts `run` with `1 + initial_clones_per_entity` strong handles.
+    retained: Vec<RcType>,
+    // Positions gathered for `Entity::update_batch`, kept to avoid
+    // allocating in the hot loop.
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    // Where `chase` goes after each entity, empty unless built with
+    // `dependent_access`.
+    chase: Vec<usize>,
+    // With `zipf`, the entities one pass reads, in order; empty otherwise.
+    access: Vec<usize>,
+    hot_set: Option<usize>,
+}
+
+impl<RcType> Game<RcType>
+where
+    RcType: CountedRc<Entity>,
+{
+    fn new(builder: &GameBuilder) -> Self {
+        let (access, hot_set) = builder.skewed_access();
+        Game {
+            entities: Vec::new(),
+            name: builder.name.clone(),
+            quiet: builder.quiet,
+            frames: builder.frames,
+            operations_per_frame: builder.operations_per_frame,
+            heavy_compute: builder.heavy_compute,
+            initial_clones_per_entity: builder.initial_clones_per_entity,
+            retain_rate: builder.retain_rate,
+            timeout: builder.timeout,
+            churn_per_frame: builder.churn_per_frame,
+            next_despawn: 0,
+            ids: builder.entity_ids(),
+            first_respawn: 0,
+            picked: Vec::new(),
+            retained: Vec::new(),
+            xs: Vec::new(),
+            ys: Vec::new(),
+            chase: builder.chase_order(),
+            access,
+            hot_set,
+        }
+    }
+
+    /// With `zipf`, how many entities take `HOT_SET_SHARE` of the reads.
+    pub fn hot_set(&self) -> Option<usize> {
+        self.hot_set
+    }
+
+    /// Creates the entities and their extra clones. Handles go straight into
+    /// the game's vectors, so if a constructor panics partway through, the
+    /// unwinding game drops exactly the handles made so far.
+    fn setup(
+        &mut self,
+        num_entities: usize,
+        seed: Option<u64>,
+        archetypes: Option<Archetypes>,
+    ) -> Result<(), BenchError> {
+        self.reserve(num_entities)?;
+        let mut rng = seed.map(SplitMix64::new);
+        match archetypes {
+            Some(archetypes) => {
+                let spawns: Vec<(usize, (f32, f32))> = (0..num_entities)
+                    .map(|_| (self.ids.next(), initial_position(&mut rng)))
+                    .collect();
+                self.setup_archetypes(&spawns, archetypes);
+            }
+            None => {
+                for _ in 0..num_entities {
+                    let id = self.ids.next();
+                    let (x, y) = initial_position(&mut rng);
+                    let entity = Entity { id, x, y };
+                    self.entities.push(RcType::clone(&RcType::new(entity)));
+                }
+            }
+        }
+        self.retain_initial_clones();
+        Ok(())
+    }
+
+    /// Spawns the `index`th entity of `spawns` (its id and position) as
+    /// part of archetype `index % archetypes.count`, and leaves the entities
+    /// sorted by archetype, so that every pass visits one archetype after
+    /// another like a system querying them. Grouped, they're allocated in
+    /// that order too; otherwise in spawn order, so each archetype's
+    /// allocations are interleaved with the others', and then sorted.
+    fn setup_archetypes(&mut self, spawns: &[(usize, (f32, f32))], archetypes: Archetypes) {
+        let spawn = |index: usize| {
+            let (id, (x, y)) = spawns[index];
+            RcType::clone(&RcType::new(Entity { id, x, y }))
+        };
+        let count = archetypes.count;
+        if archetypes.grouped {
+            for archetype in 0..count {
+                let indices = (archetype..spawns.len()).step_by(count);
+                self.entities.extend(indices.map(spawn));
+            }
+        } else {
+            let mut spawned: Vec<(usize, RcType)> = (0..spawns.len())
+                .map(|index| (index % count, spawn(index)))
+                .collect();
+            // Stable, so each archetype stays in spawn order as when grouped.
+            spawned.sort_by_key(|&(archetype, _)| archetype);
+            self.entities
+                .extend(spawned.into_iter().map(|(_, entity_rc)| entity_rc));
+        }
+    }
+
+    /// `setup`, with every entity spawned by `factory` instead.
+    fn setup_with(
+        &mut self,
+        num_entities: usize,
+        factory: &dyn Fn(usize) -> RcType,
+    ) -> Result<(), BenchError> {
+        self.reserve(num_entities)?;
+        for _ in 0..num_entities {
+            let id = self.ids.next();
+            self.entities.push(RcType::clone(&factory(id)));
+        }
+        self.retain_initial_clones();
+        Ok(())
+    }
+
+    /// Makes room for every handle setup creates in one allocation per
+    /// vector, so that no growth reallocation (and copy) is timed with it,
+    /// and at different entity counts a different number of them. Fails
+    /// with `Overflow` if a vector that long can't exist, and `AllocFailed`
+    /// if it can but doesn't fit in memory.
+    fn reserve(&mut self, num_entities: usize) -> Result<(), BenchError> {
+        // What `spreads` picks of the first `num_entities` indices.
+        let picked = (num_entities as f64 * self.retain_rate.clamp(0.0, 1.0)) as usize;
+        let retained = num_entities
+            .checked_mul(self.initial_clones_per_entity)
+            .and_then(|clones| clones.checked_add(picked))
+            .ok_or(BenchError::Overflow)?;
+        try_reserve_exact(&mut self.entities, num_entities)?;
+        try_reserve_exact(&mut self.picked, picked)?;
+        try_reserve_exact(&mut self.retained, retained)
+    }
+
+    /// `retain_rate` picks by position in the entity list, so it keeps its
+    /// share however sparse the ids are. Also marks where setup's ids end,
+    /// and which it picked, for `imbalance`.
+    fn retain_initial_clones(&mut self) {
+        self.first_respawn = self.ids.peek();
+        for (index, entity_rc) in self.entities.iter().enumerate() {
+            let picked = spreads(index, self.retain_rate);
+            if picked {
+                self.picked.push(entity_rc.id);
+            }
+            for _ in 0..self.initial_clones_per_entity + usize::from(picked) {
+                self.retained.push(entity_rc.clone());
+            }
+        }
+        // Archetypes reorder the entities, and with them the picks.
+        self.picked.sort_unstable();
+    }
+
+    /// Sum of every entity's coordinates, read through the handles.
+    pub fn checksum(&self) -> f64 {
+        self.entities
+            .iter()
+            .map(|entity| entity.x as f64 + entity.y as f64)
+            .sum()
+    }
+
+    /// Strong count of every entity's handle, in entity order.
+    pub fn strong_counts(&self) -> Vec<usize> {
+        self.entities.iter().map(CountedRc::strong_count).collect()
+    }
+
+    /// Id of every entity, in entity order.
+    pub fn ids(&self) -> Vec<usize> {
+        self.entities.iter().map(|entity_rc| entity_rc.id).collect()
+    }
+
+    /// Drops every entity's own handle, keeping the vector's buffer and the
+    /// clones in `retained`. Without retained clones every drop frees its
+    /// entity; with them every drop is only a decrement.
+    pub fn drop_entities(&mut self) {
+        self.entities.clear();
+    }
+
+    /// Reverses the entity list and `retained`, so that dropping the game
+    /// (or `drop_entities`) frees the entities back to front: in the
+    /// opposite order to the one setup allocated them in.
+    pub fn reverse_entities(&mut self) {
+        self.entities.reverse();
+        self.retained.reverse();
+    }
+
+    /// Runs the game and returns how many frames it ran: all of them, unless
+    /// the timeout ran out first. The deadline is only checked between frames.
+    pub fn run(&mut self) -> usize {
+        self.run_with_clock(&SystemClock)
+    }
+
+    /// `run`, with the timeout measured by `clock`.
+    pub fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        let deadline = self.timeout.map(|timeout| clock.now() + timeout);
+        for frame in 0..self.frames {
+            if frame > 0 && deadline.is_some_and(|deadline| clock.now() >= deadline) {
+                return frame;
+            }
+            for _ in 0..self.operations_per_frame {
+                if self.heavy_compute {
+                    self.run_batch_operation();
+                    continue;
+                }
+                if !self.access.is_empty() {
+                    // The same number of reads, mostly of the hot set.
+                    for &index in &self.access {
+                        let cloned_rc = self.entities[index].clone();
+                        let _ = std::hint::black_box(cloned_rc.x + cloned_rc.y);
+                    }
+                    continue;
+                }
+                // Iterate through entities and perform operations
+                for entity_rc in &self.entities {
+                    let cloned_rc = entity_rc.clone();
+                    let entity = cloned_rc.deref();
+                    // Perform some dummy calculations
+                    let _ = std::hint::black_box(entity.x + entity.y);
+                    // cloned_rc goes out of scope here
+                }
+            }
+            self.churn();
+            #[cfg(all(feature = "debug-checks", debug_assertions))]
+            self.check_counts(frame);
+            // Optionally, print progress
+            if !self.quiet && frame % (self.frames / 10).max(1) == 0 {
+                println!("[{}] frame {}/{}", self.name, frame, self.frames);
+            }
+        }
+        self.frames
+    }
+
+    /// Like `run` without churn or a timeout, but every read goes through
+    /// the borrowed handle instead of a clone of it: what's left is the
+    /// deref alone, so `run` minus this is what the clones cost.
+    pub fn run_no_clone(&mut self) -> usize {
+        for _ in 0..self.frames {
+            for _ in 0..self.operations_per_frame {
+                for entity_rc in &self.entities {
+                    let entity = entity_rc.deref();
+                    let _ = std::hint::black_box(entity.x + entity.y);
+                }
+            }
+        }
+        self.frames
+    }
+
+    /// Like `run` without a timeout, Zipf reads or heavy compute, but every
+    /// pass is split in two timed phases: one clones (and reads) every
+    /// entity into a buffer, the other drops the clones again. Churn, the
+    /// only thing that frees during a run, counts as dropping.
+    pub fn run_phased(&mut self, clock: &dyn Clock) -> CloneDropSplit {
+        let mut split = CloneDropSplit::default();
+        let mut clones = Vec::with_capacity(self.entities.len());
+        for _ in 0..self.frames {
+            for _ in 0..self.operations_per_frame {
+                let start = clock.now();
+                for entity_rc in &self.entities {
+                    let cloned_rc = entity_rc.clone();
+                    let _ = std::hint::black_box(cloned_rc.x + cloned_rc.y);
+                    clones.push(cloned_rc);
+                }
+                let cloned = clock.now();
+                clones.clear();
+                let dropped = clock.now();
+                split.clone += cloned.saturating_duration_since(start);
+                split.drop += dropped.saturating_duration_since(cloned);
+            }
+            let start = clock.now();
+            self.churn();
+            split.drop += clock.since(start);
+        }
+        split
+    }
+
+    /// Reads `steps` entities through cloned handles, each one picked by the
+    /// id of the one before, so no load can start until the previous one
+    /// has finished. Returns where the chase ended.
+    ///
+    /// Panics unless the game was built with `dependent_access`.
+    pub fn chase(&self, steps: usize) -> usize {
+        let len = chase_len(&self.chase, self.entities.len());
+        let mut index = 0;
+        for _ in 0..steps {
+            let cloned_rc = self.entities[index].clone();
+            let _ = std::hint::black_box(cloned_rc.x + cloned_rc.y);
+            index = self.chase[cloned_rc.id % len];
+        }
+        index
+    }
+
+    /// Checks that every clone made so far was dropped again: between
+    /// frames, every entity is held exactly by its slot in `entities` plus,
+    /// if it was set up rather than respawned, its `initial_clones_per_entity`
+    /// clones in `retained`, plus one if `retain_rate` picked it. Returns
+    /// the number of live handles.
+    pub fn check_balance(&self) -> Result<usize, BenchError> {
+        match self.imbalance() {
+            Some(details) => Err(BenchError::Unbalanced(format!(
+                "[{}] {}",
+                self.name, details
+            ))),
+            None => Ok(self.entities.len() + self.retained.len()),
+        }
+    }
+
+    /// The first entity whose strong count is off, described.
+    fn imbalance(&self) -> Option<String> {
+        if !RcType::SHARED {
+            return None;
+        }
+        self.entities.iter().find_map(|entity_rc| {
+            let expected = if entity_rc.id < self.first_respawn {
+                1 + self.initial_clones_per_entity + usize::from(self.is_picked(entity_rc.id))
+            } else {
+                1
+            };
+            let count = entity_rc.strong_count();
+            (count != expected).then(|| {
+                format!(
+                    "entity {} has strong count {}, expected {}",
+                    entity_rc.id, count, expected
+                )
+            })
+        })
+    }
+
+    /// Asserts `check_balance` after every frame.
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    fn check_counts(&self, frame: usize) {
+        if let Some(details) = self.imbalance() {
+            panic!("[{}] {} after frame {}", self.name, details, frame);
+        }
+    }
+
+    /// Whether `retain_rate` picked entity `id` at setup.
+    fn is_picked(&self, id: usize) -> bool {
+        self.picked.binary_search(&id).is_ok()
+    }
+
+    /// Despawns `churn_per_frame` entities and spawns as many new ones,
+    /// cycling through the entity list. The entities `retain_rate` picked
+    /// are passed over, so they stay for the whole game; the initial clones
+    /// held in `retained` keep any other despawned entity's allocation alive
+    /// until the end of the game.
+    fn churn(&mut self) {
+        for _ in 0..self.churn_per_frame {
+            let len = self.entities.len();
+            let Some(slot) = (0..len)
+                .map(|step| (self.next_despawn + step) % len)
+                .find(|&slot| !self.is_picked(self.entities[slot].id))
+            else {
+                return;
+            };
+            self.next_despawn = slot + 1;
+            // Despawn before spawning, so a pool can hand the block straight back.
+            drop(self.entities.swap_remove(slot));
+            let entity = Entity {
+                id: self.ids.next(),
+                x: 0.0,
+                y: 0.0,
+            };
+            self.entities.push(RcType::new(entity));
+        }
+    }
+
+    /// The heavy-compute operation: the same clone-and-read pass, gathering
+    /// positions, followed by a vectorizable update over all of them.
+    fn run_batch_operation(&mut self) {
+        self.xs.clear();
+        self.ys.clear();
+        for entity_rc in &self.entities {
+            let cloned_rc = entity_rc.clone();
+            self.xs.push(cloned_rc.x);
+            self.ys.push(cloned_rc.y);
+        }
+        let _ = std::hint::black_box(Entity::update_batch(&mut self.xs, &mut self.ys));
+    }
+}
+
+/// Where `Game::run_phased` spent its time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CloneDropSplit {
+    /// Cloning and reading every entity.
+    pub clone: Duration,
+    /// Dropping the clones, and churn.
+    pub drop: Duration,
+}
+
+impl CloneDropSplit {
+    /// Both phases together.
+    pub fn total(&self) -> Duration {
+        self.clone + self.drop
+    }
+
+    /// Share of the time spent cloning; above one half is clone-bound.
+    pub fn clone_share(&self) -> f64 {
+        self.clone.as_secs_f64() / self.total().as_secs_f64()
+    }
+}
+
+/// What `Game::run_mut_or_read` did with each access.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutOrRead {
+    /// Accesses through a unique handle, which `get_mut` let mutate.
+    pub mutated: usize,
+    /// Accesses through a shared handle, which fell back to reading.
+    pub read: usize,
+}
+
+impl MutOrRead {
+    /// Share of the accesses that took the `get_mut` fast path.
+    pub fn fast_path_share(&self) -> f64 {
+        self.mutated as f64 / (self.mutated + self.read).max(1) as f64
+    }
+}
+
+impl<RcType> Game<RcType>
+where
+    RcType: MutableRc<Entity>,
+{
+    /// Like `run` without churn or a timeout, but every pass tries
+    /// `get_mut` on each entity: a unique handle moves its entity, a shared
+    /// one only reads it. While it runs, an extra clone of an evenly spread
+    /// `shared_fraction` of the entities (clamped to 0..=1) keeps theirs
+    /// shared, on top of any retained clones.
+    pub fn run_mut_or_read(&mut self, shared_fraction: f64) -> MutOrRead {
+        let shared: Vec<RcType> = self
+            .entities
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| spreads(index, shared_fraction))
+            .map(|(_, entity_rc)| entity_rc.clone())
+            .collect();
+        let mut outcome = MutOrRead::default();
+        for _ in 0..self.frames {
+            for _ in 0..self.operations_per_frame {
+                for entity_rc in &mut self.entities {
+                    match entity_rc.get_mut() {
+                        Some(entity) => {
+                            entity.x += 1.0;
+                            outcome.mutated += 1;
+                        }
+                        None => {
+                            let _ = std::hint::black_box(entity_rc.x + entity_rc.y);
+                            outcome.read += 1;
+                        }
+                    }
+                }
+            }
+        }
+        drop(shared);
+        outcome
+    }
+}
+
+/// A random cycle through `0..len` for `chase` to follow (Sattolo's
+/// algorithm): starting anywhere, it visits every index before returning.
+fn chase_order(len: usize, seed: u64) -> Vec<usize> {
+    let mut rng = SplitMix64::new(seed);
+    let mut order: Vec<usize> = (0..len).collect();
+    for i in (1..len).rev() {
+        order.swap(i, rng.below(i));
+    }
+    order
+}
+
+/// Share of all reads the hot set reported for `zipf` covers.
+pub const HOT_SET_SHARE: f64 = 0.9;
+
+/// Spawns the entity with the given id, the way data-driven games do.
+pub type Factory<RcType> = Box<dyn Fn(usize) -> RcType>;
+
+/// One pass's worth (`len`) of entity indices for `zipf`: the entities are
+/// ranked in a random order, and each read picks rank `k` with weight
+/// `1 / (k + 1)^skew`. Also returns the hot set, the fewest ranks that take
+/// `HOT_SET_SHARE` of the weight.
+fn skewed_access(len: usize, skew: f64, seed: u64) -> (Vec<usize>, usize) {
+    let mut cdf = Vec::with_capacity(len);
+    let mut total = 0.0;
+    for rank in 0..len {
+        total += ((rank + 1) as f64).powf(-skew);
+        cdf.push(total);
+    }
+    let hot_set = (cdf.partition_point(|&weight| weight < HOT_SET_SHARE * total) + 1).min(len);
+    // Scatter the ranks over the entities, so the hot set isn't simply the
+    // first entities allocated, side by side in memory.
+    let ranked = chase_order(len, seed);
+    let mut rng = SplitMix64::new(seed.wrapping_add(1));
+    let access = (0..len)
+        .map(|_| {
+            let pick = rng.next_f64() * total;
+            ranked[cdf.partition_point(|&weight| weight <= pick).min(len - 1)]
+        })
+        .collect();
+    (access, hot_set)
+}
+
+/// The entity count a `chase` over `order` runs on, checking the game was
+/// built for one.
+fn chase_len(order: &[usize], entities: usize) -> usize {
+    assert_eq!(
+        order.len(),
+        entities,
+        "chase on a game built without dependent_access"
+    );
+    entities
+}
+
+/// `Vec::try_reserve_exact`, telling a length no allocation could hold
+/// (`Overflow`) from one the allocator refused (`AllocFailed`).
+fn try_reserve_exact<T>(vec: &mut Vec<T>, additional: usize) -> Result<(), BenchError> {
+    Layout::array::<T>(additional).map_err(|_| BenchError::Overflow)?;
+    vec.try_reserve_exact(additional)
+        .map_err(|_| BenchError::AllocFailed)
+}
+
+/// Whether `index` is one of an evenly spread `fraction` (clamped to 0..=1)
+/// of all indices: every index whose multiple of `fraction` crosses an
+/// integer.
+fn spreads(index: usize, fraction: f64) -> bool {
+    let fraction = fraction.clamp(0.0, 1.0);
+    ((index + 1) as f64 * fraction) as usize > (index as f64 * fraction) as usize
+}
+
+/// Hands out increasing entity ids, each `1 + gap` past the last, with the
+/// gaps drawn uniformly from `0..=max_gap`: contiguous from 0 with a
+/// `max_gap` of 0, spread out like the ids of a game that has been
+/// despawning otherwise.
+#[derive(Debug, Clone)]
+struct EntityIds {
+    next: usize,
+    max_gap: usize,
+    rng: SplitMix64,
+}
+
+impl EntityIds {
+    fn next(&mut self) -> usize {
+        let id = self.next;
+        let gap = if self.max_gap == 0 {
+            0
+        } else {
+            self.rng.below(self.max_gap + 1)
+        };
+        self.next += 1 + gap;
+        id
+    }
+
+    /// The id `next` will hand out.
+    fn peek(&self) -> usize {
+        self.next
+    }
+}
+
+/// Where an entity starts: drawn from `rng` if there is one, else the origin.
+fn initial_position(rng: &mut Option<SplitMix64>) -> (f32, f32) {
+    match rng {
+        // Whole-number coordinates keep the checksum exact, so any difference is real.
+        Some(rng) => (rng.below(1024) as f32, rng.below(1024) as f32),
+        None => (0.0, 0.0),
+    }
+}
+
+// ========================
+// Struct-of-Arrays Baseline
+// ========================
+
+/// The same workload as `Game`, over entities stored the way an ECS would:
+/// one column per field instead of one refcounted allocation per entity.
+/// There are no handles to clone, so each operation is just the read (or the
+/// batch update) `Game` performs behind its clone.
+pub struct SoaGame {
+    ids: Vec<usize>,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    heavy_compute: bool,
+    timeout: Option<Duration>,
+    churn_per_frame: usize,
+    next_despawn: usize,
+    next_id: usize,
+    // Copies of the columns for `Entity::update_batch`, which `Game` fills
+    // by gathering from its handles.
+    batch_xs: Vec<f32>,
+    batch_ys: Vec<f32>,
+    chase: Vec<usize>,
+    access: Vec<usize>,
+    hot_set: Option<usize>,
+}
+
+impl SoaGame {
+    /// Sum of every entity's coordinates.
+    pub fn checksum(&self) -> f64 {
+        self.xs
+            .iter()
+            .zip(&self.ys)
+            .map(|(&x, &y)| x as f64 + y as f64)
+            .sum()
+    }
+
+    /// Runs the game like `Game::run` and returns how many frames it ran.
+    pub fn run(&mut self) -> usize {
+        self.run_with_clock(&SystemClock)
+    }
+
+    /// `Game::run_with_clock`.
+    pub fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        let deadline = self.timeout.map(|timeout| clock.now() + timeout);
+        for frame in 0..self.frames {
+            if frame > 0 && deadline.is_some_and(|deadline| clock.now() >= deadline) {
+                return frame;
+            }
+            for _ in 0..self.operations_per_frame {
+                if self.heavy_compute {
+                    self.run_batch_operation();
+                    continue;
+                }
+                if !self.access.is_empty() {
+                    for &index in &self.access {
+                        let _ = std::hint::black_box(self.xs[index] + self.ys[index]);
+                    }
+                    continue;
+                }
+                for (&x, &y) in self.xs.iter().zip(&self.ys) {
+                    let _ = std::hint::black_box(x + y);
+                }
+            }
+            self.churn();
+            if !self.quiet && frame % (self.frames / 10).max(1) == 0 {
+                println!("[{}] frame {}/{}", self.name, frame, self.frames);
+            }
+        }
+        self.frames
+    }
+
+    /// `Game::hot_set`.
+    pub fn hot_set(&self) -> Option<usize> {
+        self.hot_set
+    }
+
+    /// `Game::chase`, with the id read from its column.
+    pub fn chase(&self, steps: usize) -> usize {
+        let len = chase_len(&self.chase, self.ids.len());
+        let mut index = 0;
+        for _ in 0..steps {
+            let _ = std::hint::black_box(self.xs[index] + self.ys[index]);
+            index = self.chase[self.ids[index] % len];
+        }
+        index
+    }
+
+    /// `Game::churn`, column by column.
+    fn churn(&mut self) {
+        for _ in 0..self.churn_per_frame {
+            let slot = self.next_despawn % self.ids.len();
+            self.next_despawn = slot + 1;
+            self.ids.swap_remove(slot);
+            self.xs.swap_remove(slot);
+            self.ys.swap_remove(slot);
+            self.ids.push(self.next_id);
+            self.xs.push(0.0);
+            self.ys.push(0.0);
+            self.next_id += 1;
+        }
+    }
+
+    /// `Game::run_batch_operation`, with a copy in place of the gather.
+    fn run_batch_operation(&mut self) {
+        self.batch_xs.clear();
+        self.batch_ys.clear();
+        self.batch_xs.extend_from_slice(&self.xs);
+        self.batch_ys.extend_from_slice(&self.ys);
+        let _ = std::hint::black_box(Entity::update_batch(&mut self.batch_xs, &mut self.batch_ys));
+    }
+}
+
+// ========================
+// Count by Hand
+// ========================
+
+/// `Game` with the refcounting done by hand: the entities sit in a plain
+/// vector next to a vector of their counts, and a "clone" is an increment of
+/// the entity's count, its "drop" a decrement (with the check for zero a
+/// real drop makes). No pointer, no `Deref`, no `Drop`: whatever `Game`
+/// spends beyond this is the abstraction's. The counts are `Cell`s only so
+/// that sharing works through `&self`, as `Rc::clone` does.
+pub struct ManualGame {
+    entities: Vec<Entity>,
+    counts: Vec<Cell<usize>>,
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    heavy_compute: bool,
+    initial_clones_per_entity: usize,
+    retain_rate: f64,
+    timeout: Option<Duration>,
+    churn_per_frame: usize,
+    next_despawn: usize,
+    next_id: usize,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    chase: Vec<usize>,
+    access: Vec<usize>,
+    hot_set: Option<usize>,
+}
+
+impl ManualGame {
+    /// Sum of every entity's coordinates.
+    pub fn checksum(&self) -> f64 {
+        self.entities
+            .iter()
+            .map(|entity| entity.x as f64 + entity.y as f64)
+            .sum()
+    }
+
+    /// Runs the game like `Game::run` and returns how many frames it ran.
+    pub fn run(&mut self) -> usize {
+        self.run_with_clock(&SystemClock)
+    }
+
+    /// `Game::run_with_clock`.
+    pub fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        let deadline = self.timeout.map(|timeout| clock.now() + timeout);
+        for frame in 0..self.frames {
+            if frame > 0 && deadline.is_some_and(|deadline| clock.now() >= deadline) {
+                return frame;
+            }
+            for _ in 0..self.operations_per_frame {
+                if self.heavy_compute {
+                    self.run_batch_operation();
+                    continue;
+                }
+                if !self.access.is_empty() {
+                    for &index in &self.access {
+                        let entity = self.share(index);
+                        let _ = std::hint::black_box(entity.x + entity.y);
+                        self.release(index);
+                    }
+                    continue;
+                }
+                for index in 0..self.entities.len() {
+                    let entity = self.share(index);
+                    let _ = std::hint::black_box(entity.x + entity.y);
+                    self.release(index);
+                }
+            }
+            self.churn();
+            if !self.quiet && frame % (self.frames / 10).max(1) == 0 {
+                println!("[{}] frame {}/{}", self.name, frame, self.frames);
+            }
+        }
+        self.frames
+    }
+
+    /// The clone: counts one more holder of entity `index` and reads it.
+    #[inline]
+    fn share(&self, index: usize) -> Entity {
+        let count = &self.counts[index];
+        count.set(count.get() + 1);
+        self.entities[index]
+    }
+
+    /// The drop. The slot always holds the entity too, so nothing shared is
+    /// ever released by its last holder, but the check for it is paid like
+    /// in every `Drop`.
+    #[inline]
+    fn release(&self, index: usize) {
+        let count = &self.counts[index];
+        count.set(count.get() - 1);
+        if count.get() == 0 {
+            panic!(
+                "[{}] entity {} released by its last holder",
+                self.name, self.entities[index].id
+            );
+        }
+    }
+
+    fn despawn(&mut self, index: usize) {
+        self.entities.swap_remove(index);
+        self.counts.swap_remove(index);
+    }
+
+    /// `Game::hot_set`.
+    pub fn hot_set(&self) -> Option<usize> {
+        self.hot_set
+    }
+
+    /// `Game::chase`, sharing and releasing each entity it reads.
+    pub fn chase(&self, steps: usize) -> usize {
+        let len = chase_len(&self.chase, self.entities.len());
+        let mut index = 0;
+        for _ in 0..steps {
+            let entity = self.share(index);
+            let _ = std::hint::black_box(entity.x + entity.y);
+            self.release(index);
+            index = self.chase[entity.id % len];
+        }
+        index
+    }
+
+    /// `Game::check_balance`, over the counts: every entity must be back to
+    /// its slot plus whatever `setup` retained for it.
+    pub fn check_balance(&self) -> Result<usize, BenchError> {
+        let set_up = self.entities.len();
+        let imbalance = self
+            .entities
+            .iter()
+            .zip(&self.counts)
+            .find(|&(entity, count)| {
+                let expected = if entity.id < set_up {
+                    1 + self.initial_clones_per_entity
+                        + usize::from(spreads(entity.id, self.retain_rate))
+                } else {
+                    1
+                };
+                count.get() != expected
+            });
+        match imbalance {
+            Some((entity, count)) => Err(BenchError::Unbalanced(format!(
+                "[{}] entity {} has count {}",
+                self.name,
+                entity.id,
+                count.get()
+            ))),
+            None => Ok(self.counts.iter().map(Cell::get).sum()),
+        }
+    }
+
+    /// `Game::churn`: the despawned entity gives up its own count and, like
+    /// any other, goes once nothing holds it. Retained holders are only
+    /// numbers here, so there's nothing left to keep it around for. The
+    /// entities `retain_rate` picked are passed over, as there.
+    fn churn(&mut self) {
+        let set_up = self.entities.len();
+        for _ in 0..self.churn_per_frame {
+            let len = self.entities.len();
+            let Some(slot) = (0..len)
+                .map(|step| (self.next_despawn + step) % len)
+                .find(|&slot| {
+                    let id = self.entities[slot].id;
+                    id >= set_up || !spreads(id, self.retain_rate)
+                })
+            else {
+                return;
+            };
+            self.next_despawn = slot + 1;
+            self.despawn(slot);
+            self.entities.push(Entity {
+                id: self.next_id,
+                x: 0.0,
+                y: 0.0,
+            });
+            self.counts.push(Cell::new(1));
+            self.next_id += 1;
+        }
+    }
+
+    /// `Game::run_batch_operation`, sharing each entity while it's gathered.
+    fn run_batch_operation(&mut self) {
+        self.xs.clear();
+        self.ys.clear();
+        for index in 0..self.entities.len() {
+            let entity = self.share(index);
+            self.xs.push(entity.x);
+            self.ys.push(entity.y);
+            self.release(index);
+        }
+        let _ = std::hint::black_box(Entity::update_batch(&mut self.xs, &mut self.ys));
+    }
+}
+
+// ========================
+// Read Through Weak Views
+// ========================
+
+/// `Game` as seen by a subsystem that doesn't own the entities: it reads
+/// them through weak handles, while the strong ones sit in a master list
+/// elsewhere. Every read upgrades, reads and drops the temporary strong.
+pub struct WeakGame<RcType: WeakRc<Entity>> {
+    /// Owns the entities; `despawn` drops them from here.
+    master: Vec<RcType>,
+    views: Vec<RcType::Weak>,
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    failed_upgrades: usize,
+}
+
+impl<RcType: WeakRc<Entity>> WeakGame<RcType> {
+    /// Sum of the coordinates of every entity still alive.
+    pub fn checksum(&self) -> f64 {
+        self.master
+            .iter()
+            .map(|entity_rc| entity_rc.x as f64 + entity_rc.y as f64)
+            .sum()
+    }
+
+    /// Like `run` without churn, a timeout, Zipf reads or heavy compute,
+    /// with every read upgrading a view. Views whose entity is gone are
+    /// skipped and counted in `failed_upgrades`. Returns how many frames
+    /// it ran.
+    pub fn run(&mut self) -> usize {
+        for frame in 0..self.frames {
+            for _ in 0..self.operations_per_frame {
+                for view in &self.views {
+                    match RcType::upgrade(view) {
+                        Some(entity_rc) => {
+                            let _ = std::hint::black_box(entity_rc.x + entity_rc.y);
+                        }
+                        None => self.failed_upgrades += 1,
+                    }
+                }
+            }
+            if !self.quiet && frame % (self.frames / 10).max(1) == 0 {
+                println!("[{}] frame {}/{}", self.name, frame, self.frames);
+            }
+        }
+        self.frames
+    }
+
+    /// Drops every handle the master list has to entity `id`, its retained
+    /// clones included, which frees it unless something else still holds
+    /// it; its view stays behind. Returns whether the entity was in the
+    /// master list.
+    pub fn despawn(&mut self, id: usize) -> bool {
+        let len = self.master.len();
+        self.master.retain(|entity_rc| entity_rc.id != id);
+        self.master.len() < len
+    }
+
+    /// Upgrades that found their entity gone, over every `run` so far.
+    pub fn failed_upgrades(&self) -> usize {
+        self.failed_upgrades
+    }
+}
+
+// ========================
+// Update Entities in Place
+// ========================
+
+/// `Game`, except every operation also updates the entity it cloned a handle
+/// to, through the interior mutability `E` provides. The heavy-compute batch
+/// is `Game`'s, gathered with `SharedEntity::get`.
+pub struct UpdateGame<E: SharedEntity> {
+    entities: Vec<E>,
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    heavy_compute: bool,
+    timeout: Option<Duration>,
+    churn_per_frame: usize,
+    next_despawn: usize,
+    next_id: usize,
+    retained: Vec<E>,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    chase: Vec<usize>,
+    access: Vec<usize>,
+    hot_set: Option<usize>,
+}
+
+impl<E: SharedEntity> UpdateGame<E> {
+    /// Sum of every entity's coordinates.
+    pub fn checksum(&self) -> f64 {
+        self.entities
+            .iter()
+            .map(|entity| {
+                let entity = entity.get();
+                entity.x as f64 + entity.y as f64
+            })
+            .sum()
+    }
+
+    /// Runs the game like `Game::run` and returns how many frames it ran.
+    pub fn run(&mut self) -> usize {
+        self.run_with_clock(&SystemClock)
+    }
+
+    /// `Game::run_with_clock`.
+    pub fn run_with_clock(&mut self, clock: &dyn Clock) -> usize {
+        let deadline = self.timeout.map(|timeout| clock.now() + timeout);
+        for frame in 0..self.frames {
+            if frame > 0 && deadline.is_some_and(|deadline| clock.now() >= deadline) {
+                return frame;
+            }
+            for _ in 0..self.operations_per_frame {
+                if self.heavy_compute {
+                    self.run_batch_operation();
+                    continue;
+                }
+                if !self.access.is_empty() {
+                    for &index in &self.access {
+                        let entity = self.entities[index].clone().update();
+                        let _ = std::hint::black_box(entity.x + entity.y);
+                    }
+                    continue;
+                }
+                for entity_rc in &self.entities {
+                    let cloned_rc = entity_rc.clone();
+                    let entity = cloned_rc.update();
+                    let _ = std::hint::black_box(entity.x + entity.y);
+                }
+            }
+            self.churn();
+            if !self.quiet && frame % (self.frames / 10).max(1) == 0 {
+                println!("[{}] frame {}/{}", self.name, frame, self.frames);
+            }
+        }
+        self.frames
+    }
+
+    /// `Game::hot_set`.
+    pub fn hot_set(&self) -> Option<usize> {
+        self.hot_set
+    }
+
+    /// `Game::chase`, reading with `SharedEntity::get`.
+    pub fn chase(&self, steps: usize) -> usize {
+        let len = chase_len(&self.chase, self.entities.len());
+        let mut index = 0;
+        for _ in 0..steps {
+            let entity = self.entities[index].clone().get();
+            let _ = std::hint::black_box(entity.x + entity.y);
+            index = self.chase[entity.id % len];
+        }
+        index
+    }
+
+    /// `Game::churn`.
+    fn churn(&mut self) {
+        for _ in 0..self.churn_per_frame {
+            let slot = self.next_despawn % self.entities.len();
+            self.next_despawn = slot + 1;
+            drop(self.entities.swap_remove(slot));
+            self.entities.push(E::new(Entity {
+                id: self.next_id,
+                x: 0.0,
+                y: 0.0,
+            }));
+            self.next_id += 1;
+        }
+    }
+
+    /// `Game::run_batch_operation`.
+    fn run_batch_operation(&mut self) {
+        self.xs.clear();
+        self.ys.clear();
+        for entity_rc in &self.entities {
+            let entity = entity_rc.clone().get();
+            self.xs.push(entity.x);
+            self.ys.push(entity.y);
+        }
+        let _ = std::hint::black_box(Entity::update_batch(&mut self.xs, &mut self.ys));
+    }
+}
+
+// ========================
+// Build a Validated Game
+// ========================
+
+/// How `setup` lays out entities split into `count` archetypes; see
+/// `GameBuilder::archetypes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Archetypes {
+    pub count: usize,
+    /// Allocate each archetype's entities together rather than interleaved.
+    pub grouped: bool,
+}
+
+/// Validates the game settings and sets up the entities in one step.
+#[derive(Debug, Clone)]
+pub struct GameBuilder {
+    name: String,
+    quiet: bool,
+    frames: usize,
+    operations_per_frame: usize,
+    num_entities: usize,
+    heavy_compute: bool,
+    initial_clones_per_entity: usize,
+    retain_rate: f64,
+    seed: Option<u64>,
+    timeout: Option<Duration>,
+    churn_per_frame: usize,
+    dependent_access: bool,
+    zipf: Option<f64>,
+    archetypes: Option<Archetypes>,
+    max_id_gap: usize,
+}
+
+impl Default for GameBuilder {
+    fn default() -> Self {
+        GameBuilder::from_config(&Config::default())
+    }
+}
+
+impl GameBuilder {
+    pub fn from_config(config: &Config) -> Self {
+        GameBuilder {
+            name: String::from("Game"),
+            quiet: config.quiet,
+            frames: config.num_frames,
+            operations_per_frame: config.operations_per_frame,
+            num_entities: config.num_entities,
+            heavy_compute: config.heavy_compute,
+            initial_clones_per_entity: config.initial_clones_per_entity,
+            retain_rate: config.retain_rate,
+            seed: None,
+            timeout: config.timeout,
+            churn_per_frame: config.churn_per_frame,
+            dependent_access: config.dependent_access,
+            zipf: config.zipf,
+            archetypes: None,
+            max_id_gap: config.max_id_gap,
+        }
+    }
+
+    /// Sets the prefix of the progress lines printed by `run`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    pub fn operations_per_frame(mut self, operations_per_frame: usize) -> Self {
+        self.operations_per_frame = operations_per_frame;
+        self
+    }
+
+    pub fn entities(mut self, num_entities: usize) -> Self {
+        self.num_entities = num_entities;
+        self
+    }
+
+    /// Swaps the light `x + y` read for the heavier `Entity::update_batch`.
+    pub fn heavy_compute(mut self, heavy_compute: bool) -> Self {
+        self.heavy_compute = heavy_compute;
+        self
+    }
+
+    /// Makes `setup` keep this many extra clones of every entity alive for the
+    /// whole game, so clones and drops in `run` operate on higher counts.
+    pub fn initial_clones_per_entity(mut self, clones: usize) -> Self {
+        self.initial_clones_per_entity = clones;
+        self
+    }
+
+    /// Makes `setup` keep one more clone of an evenly spread `rate` of the
+    /// entities for the whole game, so their counts stay above one, and
+    /// leaves them out of churn while the rest still despawn and free. Only
+    /// `Game` has handles to keep.
+    pub fn retain_rate(mut self, rate: f64) -> Self {
+        self.retain_rate = rate;
+        self
+    }
+
+    /// Starts the entities at positions drawn from `seed` instead of the origin,
+    /// so two games built with the same seed are directly comparable.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Despawns and respawns this many entities at the end of every frame,
+    /// so `run` also exercises allocation and freeing.
+    pub fn churn_per_frame(mut self, churn: usize) -> Self {
+        self.churn_per_frame = churn;
+        self
+    }
+
+    /// Prepares the game for `chase`, the dependent-access pass.
+    pub fn dependent_access(mut self, dependent_access: bool) -> Self {
+        self.dependent_access = dependent_access;
+        self
+    }
+
+    /// Makes every pass of `run` read a Zipf-skewed sample of the entities
+    /// (as many reads as there are entities) instead of each one once, so a
+    /// small hot set stays cached. Larger `skew` means a smaller hot set. The
+    /// heavy-compute batch still gathers every entity.
+    pub fn zipf(mut self, skew: f64) -> Self {
+        self.zipf = Some(skew);
+        self
+    }
+
+    /// Spaces `Game`'s entity ids apart, setup's and churn's alike, by gaps
+    /// of up to `max_gap`, so that whatever they key (the `chase` table, a
+    /// `Factory`'s spawn table) sees them sparse.
+    /// The games without handles always number their entities from 0.
+    pub fn id_gaps(mut self, max_gap: usize) -> Self {
+        self.max_id_gap = max_gap;
+        self
+    }
+
+    /// Splits the entities into `count` archetypes that `run` visits one
+    /// after another, with the allocations of each either `grouped`
+    /// together or interleaved with the others'. Only the allocation order
+    /// differs: the entities and the order they're read in are the same.
+    pub fn archetypes(mut self, count: usize, grouped: bool) -> Self {
+        self.archetypes = Some(Archetypes { count, grouped });
+        self
+    }
+
+    /// Lets `run` stop early, between frames, once it has taken `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn build<RcType>(self) -> Result<Game<RcType>, BenchError>
+    where
+        RcType: CountedRc<Entity>,
+    {
+        self.validate()?;
+        let mut game = Game::new(&self);
+        game.setup(self.num_entities, self.seed, self.archetypes)?;
+        Ok(game)
+    }
+
+    /// `build`, but with every entity spawned through `factory`'s dynamic
+    /// call instead of a monomorphized `Constructor::new`.
+    pub fn build_with_factory<RcType>(
+        self,
+        factory: &dyn Fn(usize) -> RcType,
+    ) -> Result<Game<RcType>, BenchError>
+    where
+        RcType: CountedRc<Entity>,
+    {
+        self.validate()?;
+        let mut game = Game::new(&self);
+        game.setup_with(self.num_entities, factory)?;
+        Ok(game)
+    }
+
+    /// A factory spawning the same entities as `build`. Their starting
+    /// positions are looked up in a spawn table that is filled here, so
+    /// drawing them isn't part of the setup the factory is used in.
+    ///
+    /// The table holds one `(id, position)` per entity in spawn order, so it
+    /// stays as long as the entity list however sparse `id_gaps` makes the
+    /// ids. Contiguous ids are found at their own index; others by a binary
+    /// search, since the ids only increase.
+    pub fn factory<RcType>(&self) -> Factory<RcType>
+    where
+        RcType: Constructor<Entity> + 'static,
+    {
+        let mut rng = self.seed.map(SplitMix64::new);
+        let mut ids = self.entity_ids();
+        let spawns: Vec<(usize, (f32, f32))> = (0..self.num_entities)
+            .map(|_| (ids.next(), initial_position(&mut rng)))
+            .collect();
+        Box::new(move |id| {
+            let (x, y) = match spawns.get(id) {
+                Some(&(spawned, position)) if spawned == id => position,
+                _ => spawns
+                    .binary_search_by_key(&id, |&(spawned, _)| spawned)
+                    .map_or_else(|_| Default::default(), |index| spawns[index].1),
+            };
+            RcType::new(Entity { id, x, y })
+        })
+    }
+
+    /// Builds the struct-of-arrays version of the same game, with the same
+    /// starting positions. There are no handles, so
+    /// `initial_clones_per_entity` has nothing to apply to.
+    pub fn build_soa(self) -> Result<SoaGame, BenchError> {
+        self.validate()?;
+        let chase = self.chase_order();
+        let (access, hot_set) = self.skewed_access();
+        let mut rng = self.seed.map(SplitMix64::new);
+        let (xs, ys) = (0..self.num_entities)
+            .map(|_| initial_position(&mut rng))
+            .unzip();
+        Ok(SoaGame {
+            ids: (0..self.num_entities).collect(),
+            xs,
+            ys,
+            name: self.name,
+            quiet: self.quiet,
+            frames: self.frames,
+            operations_per_frame: self.operations_per_frame,
+            heavy_compute: self.heavy_compute,
+            timeout: self.timeout,
+            churn_per_frame: self.churn_per_frame,
+            next_despawn: 0,
+            next_id: self.num_entities,
+            batch_xs: Vec::new(),
+            batch_ys: Vec::new(),
+            chase,
+            access,
+            hot_set,
+        })
+    }
+
+    /// Builds the same game as `build`, but keeps the handles it sets up in
+    /// a master list and reads through weak views of them instead.
+    pub fn build_weak<RcType>(self) -> Result<WeakGame<RcType>, BenchError>
+    where
+        RcType: WeakRc<Entity>,
+    {
+        let (name, quiet, frames, operations_per_frame) = (
+            self.name.clone(),
+            self.quiet,
+            self.frames,
+            self.operations_per_frame,
+        );
+        let Game {
+            entities, retained, ..
+        } = self.build::<RcType>()?;
+        let views = entities.iter().map(RcType::downgrade).collect();
+        let mut master = entities;
+        master.extend(retained);
+        Ok(WeakGame {
+            master,
+            views,
+            name,
+            quiet,
+            frames,
+            operations_per_frame,
+            failed_upgrades: 0,
+        })
+    }
+
+    /// Builds the hand-counted version of the same game, with the same
+    /// starting positions and the counts `build` would leave.
+    pub fn build_manual(self) -> Result<ManualGame, BenchError> {
+        self.validate()?;
+        let chase = self.chase_order();
+        let (access, hot_set) = self.skewed_access();
+        let mut rng = self.seed.map(SplitMix64::new);
+        let entities: Vec<Entity> = (0..self.num_entities)
+            .map(|id| {
+                let (x, y) = initial_position(&mut rng);
+                Entity { id, x, y }
+            })
+            .collect();
+        let counts = (0..self.num_entities)
+            .map(|id| {
+                Cell::new(
+                    1 + self.initial_clones_per_entity + usize::from(spreads(id, self.retain_rate)),
+                )
+            })
+            .collect();
+        Ok(ManualGame {
+            entities,
+            counts,
+            name: self.name,
+            quiet: self.quiet,
+            frames: self.frames,
+            operations_per_frame: self.operations_per_frame,
+            heavy_compute: self.heavy_compute,
+            initial_clones_per_entity: self.initial_clones_per_entity,
+            retain_rate: self.retain_rate,
+            timeout: self.timeout,
+            churn_per_frame: self.churn_per_frame,
+            next_despawn: 0,
+            next_id: self.num_entities,
+            xs: Vec::new(),
+            ys: Vec::new(),
+            chase,
+            access,
+            hot_set,
+        })
+    }
+
+    /// Builds the in-place-update version of the game, with the same setup
+    /// as `build`.
+    pub fn build_update<E: SharedEntity>(self) -> Result<UpdateGame<E>, BenchError> {
+        self.validate()?;
+        let chase = self.chase_order();
+        let (access, hot_set) = self.skewed_access();
+        let retained = self
+            .num_entities
+            .checked_mul(self.initial_clones_per_entity)
+            .ok_or(BenchError::Overflow)?;
+        let mut retained_clones = Vec::new();
+        try_reserve_exact(&mut retained_clones, retained)?;
+        let mut rng = self.seed.map(SplitMix64::new);
+        let entities: Vec<E> = (0..self.num_entities)
+            .map(|id| {
+                let (x, y) = initial_position(&mut rng);
+                E::new(Entity { id, x, y })
+            })
+            .collect();
+        let mut game = UpdateGame {
+            entities,
+            name: self.name,
+            quiet: self.quiet,
+            frames: self.frames,
+            operations_per_frame: self.operations_per_frame,
+            heavy_compute: self.heavy_compute,
+            timeout: self.timeout,
+            churn_per_frame: self.churn_per_frame,
+            next_despawn: 0,
+            next_id: self.num_entities,
+            retained: retained_clones,
+            xs: Vec::new(),
+            ys: Vec::new(),
+            chase,
+            access,
+            hot_set,
+        };
+        for entity_rc in &game.entities {
+            for _ in 0..self.initial_clones_per_entity {
+                game.retained.push(entity_rc.clone());
+            }
+        }
+        Ok(game)
+    }
+
+    /// The game's `chase` order, if it needs one. It's drawn from the seed,
+    /// so games built alike chase alike.
+    fn chase_order(&self) -> Vec<usize> {
+        if self.dependent_access {
+            chase_order(self.num_entities, self.seed.unwrap_or(0))
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The ids `Game` spawns its entities with.
+    fn entity_ids(&self) -> EntityIds {
+        EntityIds {
+            next: 0,
+            max_gap: self.max_id_gap,
+            rng: SplitMix64::new(self.seed.unwrap_or(0)),
+        }
+    }
+
+    /// The `zipf` access order and hot set, if skewed.
+    fn skewed_access(&self) -> (Vec<usize>, Option<usize>) {
+        match self.zipf {
+            Some(skew) => {
+                let (access, hot_set) =
+                    skewed_access(self.num_entities, skew, self.seed.unwrap_or(0));
+                (access, Some(hot_set))
+            }
+            None => (Vec::new(), None),
+        }
+    }
+
+    fn validate(&self) -> Result<(), BenchError> {
+        if self.frames == 0 {
+            return Err(BenchError::InvalidFrames);
+        }
+        if self.num_entities == 0 {
+            return Err(BenchError::InvalidEntities);
+        }
+        if let Some(skew) = self.zipf.filter(|skew| !(*skew > 0.0 && skew.is_finite())) {
+            return Err(BenchError::InvalidArgument(format!("zipf skew {}", skew)));
+        }
+        if !(0.0..=1.0).contains(&self.retain_rate) {
+            return Err(BenchError::InvalidArgument(format!(
+                "retain rate {}",
+                self.retain_rate
+            )));
+        }
+        if self
+            .archetypes
+            .is_some_and(|archetypes| archetypes.count == 0)
+        {
+            return Err(BenchError::InvalidArgument("0 archetypes".to_string()));
+        }
+        // Even with every gap at its widest, the last id churn spawns must fit.
+        self.frames
+            .checked_mul(self.churn_per_frame)
+            .and_then(|respawns| respawns.checked_add(self.num_entities))
+            .and_then(|spawns| spawns.checked_mul(self.max_id_gap.checked_add(1)?))
+            .ok_or(BenchError::Overflow)?;
+        total_operations(self.frames, self.operations_per_frame, self.num_entities).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::rc::{CustomRc, StdRcWrapper};
+
+    /// A small, quiet game: few enough entities and frames for Miri.
+    fn small() -> GameBuilder {
+        GameBuilder::default()
+            .quiet(true)
+            .entities(16)
+            .frames(2)
+            .operations_per_frame(2)
+            .initial_clones_per_entity(0)
+            .retain_rate(0.0)
+            .churn_per_frame(0)
+    }
+
+    #[test]
+    fn setup_holds_the_configured_clones_of_every_entity() {
+        let game = small()
+            .initial_clones_per_entity(3)
+            .build::<CustomRc<Entity>>()
+            .unwrap();
+        assert_eq!(game.strong_counts(), vec![4; 16]);
+        let game = small()
+            .initial_clones_per_entity(3)
+            .build::<StdRcWrapper<Entity>>()
+            .unwrap();
+        assert_eq!(game.strong_counts(), vec![4; 16]);
+    }
+
+    #[test]
+    fn a_game_too_big_to_count_is_refused_before_setup() {
+        let built = small()
+            .frames(usize::MAX / 2)
+            .operations_per_frame(3)
+            .build::<CustomRc<Entity>>();
+        assert_eq!(built.err(), Some(BenchError::Overflow));
+        let built = small()
+            .initial_clones_per_entity(usize::MAX)
+            .build::<CustomRc<Entity>>();
+        assert_eq!(built.err(), Some(BenchError::Overflow));
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "entity 0 has strong count 2, expected 1 after frame 0")]
+    fn debug_checks_catch_a_clone_held_past_a_frame() {
+        let mut game = small().build::<CustomRc<Entity>>().unwrap();
+        let stray = game.entities[0].clone();
+        game.retained.push(stray);
+        game.run();
+    }
+
+    #[test]
+    fn the_balance_check_reports_a_clone_that_was_never_dropped() {
+        let game = small()
+            .initial_clones_per_entity(1)
+            .build::<CustomRc<Entity>>()
+            .unwrap();
+        assert_eq!(game.check_balance(), Ok(32));
+        let stray = game.entities[3].clone();
+        assert_eq!(
+            game.check_balance(),
+            Err(BenchError::Unbalanced(
+                "[Game] entity 3 has strong count 3, expected 2".to_string()
+            ))
+        );
+        drop(stray);
+        assert_eq!(game.check_balance(), Ok(32));
+    }
+
+    #[test]
+    fn setup_reserves_every_handle_up_front() {
+        let game = small()
+            .initial_clones_per_entity(2)
+            .retain_rate(0.25)
+            .build::<CustomRc<Entity>>()
+            .unwrap();
+        assert_eq!(game.entities.capacity(), 16);
+        assert_eq!(game.retained.len(), 16 * 2 + 4);
+        assert_eq!(game.retained.capacity(), game.retained.len());
+    }
+
+    #[test]
+    fn handles_that_cannot_fit_are_refused_instead_of_panicking() {
+        let built = small()
+            .initial_clones_per_entity(1_000_000_000_000_000_000)
+            .build::<CustomRc<Entity>>();
+        assert_eq!(built.err(), Some(BenchError::Overflow));
+        let factory = small().factory::<CustomRc<Entity>>();
+        let built = small()
+            .initial_clones_per_entity(1_000_000_000_000_000_000)
+            .build_with_factory(&factory);
+        assert_eq!(built.err(), Some(BenchError::Overflow));
+    }
+
+    #[test]
+    fn the_retain_rate_keeps_an_extra_clone_of_that_share() {
+        let mut game = small()
+            .entities(100)
+            .retain_rate(0.25)
+            .churn_per_frame(30)
+            .frames(1)
+            .build::<CustomRc<Entity>>()
+            .unwrap();
+        let retained = |game: &Game<CustomRc<Entity>>| {
+            let mut ids: Vec<usize> = game
+                .ids()
+                .into_iter()
+                .zip(game.strong_counts())
+                .filter(|&(_, count)| count > 1)
+                .map(|(id, _)| id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        let picked = retained(&game);
+        assert_eq!(picked.len(), 25);
+        for _ in 0..3 {
+            assert_eq!(game.run(), 1);
+            assert_eq!(retained(&game), picked);
+        }
+        // 90 despawns went around the other 75 and into their respawns.
+        let churned = game.ids().into_iter().filter(|id| !picked.contains(id));
+        assert!(churned.clone().all(|id| id >= 100));
+        assert_eq!(churned.count(), 75);
+        assert_eq!(game.check_balance(), Ok(125));
+    }
+
+    #[test]
+    fn the_phased_run_splits_every_timed_step() {
+        let mut game = small()
+            .churn_per_frame(4)
+            .build::<CustomRc<Entity>>()
+            .unwrap();
+        let clock = MockClock::new(Duration::from_millis(1));
+        let split = game.run_phased(&clock);
+        // A step per pass to clone and one to drop (2 frames x 2 ops), plus
+        // a step of churn per frame.
+        assert_eq!(split.clone, Duration::from_millis(4));
+        assert_eq!(split.drop, Duration::from_millis(6));
+        assert_eq!(split.total(), Duration::from_millis(10));
+        assert_eq!(split.clone_share(), 0.4);
+        assert_eq!(game.check_balance(), Ok(16));
+    }
+
+    #[test]
+    fn despawned_entities_fail_their_upgrades() {
+        let mut game = small()
+            .initial_clones_per_entity(2)
+            .retain_rate(0.5)
+            .build_weak::<CustomRc<Entity>>()
+            .unwrap();
+        assert_eq!(game.run(), 2);
+        assert_eq!(game.failed_upgrades(), 0);
+        assert!(game.despawn(3));
+        assert!(game.despawn(7));
+        assert!(!game.despawn(3));
+        game.run();
+        // 2 entities x 2 frames x 2 operations.
+        assert_eq!(game.failed_upgrades(), 8);
+    }
+
+    #[test]
+    fn the_factory_spawns_what_build_does_however_sparse_the_ids() {
+        for max_gap in [0, 3, usize::MAX / 64] {
+            let builder = small().seed(7).id_gaps(max_gap);
+            let built = builder.clone().build::<CustomRc<Entity>>().unwrap();
+            let factory = builder.factory::<CustomRc<Entity>>();
+            let spawned = builder.build_with_factory(&factory).unwrap();
+            assert_eq!(spawned.ids(), built.ids());
+            assert_eq!(spawned.checksum(), built.checksum());
+            assert!(built.checksum() != 0.0);
+        }
+    }
+
+    #[test]
+    fn gapped_ids_are_increasing_bounded_and_seeded() {
+        let ids = |seed, max_gap| {
+            small()
+                .seed(seed)
+                .id_gaps(max_gap)
+                .build::<CustomRc<Entity>>()
+                .unwrap()
+                .ids()
+        };
+        assert_eq!(ids(1, 0), (0..16).collect::<Vec<_>>());
+        let gapped = ids(1, 5);
+        assert_eq!(gapped[0], 0);
+        assert!(gapped
+            .windows(2)
+            .all(|pair| (1..=6).contains(&(pair[1] - pair[0]))));
+        assert!(gapped[15] > 15);
+        assert_eq!(ids(1, 5), gapped);
+        assert!(ids(2, 5) != gapped);
+        let built = small().id_gaps(usize::MAX).build::<CustomRc<Entity>>();
+        assert_eq!(built.err(), Some(BenchError::Overflow));
+    }
+
+    #[test]
+    fn the_retain_rate_keeps_its_share_of_gapped_ids() {
+        for archetypes in [None, Some(false), Some(true)] {
+            let mut builder = small()
+                .entities(100)
+                .id_gaps(7)
+                .retain_rate(0.3)
+                .churn_per_frame(5)
+                .frames(4);
+            if let Some(grouped) = archetypes {
+                builder = builder.archetypes(3, grouped);
+            }
+            let mut game = builder.build::<CustomRc<Entity>>().unwrap();
+            let picked = game
+                .strong_counts()
+                .iter()
+                .filter(|&&count| count > 1)
+                .count();
+            assert_eq!(picked, 30);
+            assert_eq!(game.run(), 4);
+            assert_eq!(game.check_balance(), Ok(130));
+        }
+    }
+}
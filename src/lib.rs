@@ -0,0 +1,84 @@
+//! A playground for comparing reference-counted smart pointer implementations
+//! under a simple game-like workload.
+
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod allocator;
+pub mod arc;
+pub mod arena;
+pub mod bench;
+pub mod cell;
+pub mod clock;
+pub mod command;
+pub mod config;
+pub mod copy;
+pub mod entity;
+pub mod equivalence;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod game;
+pub mod micro;
+pub mod named;
+#[cfg(feature = "observer")]
+pub mod observer;
+pub mod parallel;
+pub mod pool;
+pub mod rc;
+pub mod registry;
+pub mod report;
+pub mod rng;
+pub mod rss;
+pub mod small;
+pub mod storm;
+pub mod trace;
+
+pub use allocator::{BenchAllocator, SizeHistogram};
+pub use arc::{ArcCount, BiasedArc, CustomArc, PaddedArc, PaddedCount};
+pub use arena::{with_entity_arena, ArenaId, ArenaRc, GenRepr, HandleRepr, IndexRepr, PtrRepr};
+pub use bench::{
+    bench_handoff, benchmark, benchmark_factory, benchmark_implementation, benchmark_soa,
+    benchmark_update, check_clone_allocation_free, compare_setup_allocators, cross_check,
+    run_archetype_benchmark, run_clone_drop_benchmark, run_clone_tax_benchmark,
+    run_drop_order_benchmark, run_handoff_benchmark, run_mut_or_read_benchmark,
+    run_weak_game_benchmark, BenchResult, Bencher, HandoffTiming, RunStatus,
+};
+pub use cell::{CellEntity, RefCellEntity, SharedEntity};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use command::Command;
+pub use config::{Config, Implementation, OutputFormat};
+pub use copy::CopyRc;
+pub use entity::{Entity, NamedEntity};
+pub use equivalence::{fuzz_equivalence, stress_scopes, Op, OpLog, ReplayState};
+pub use error::{AllocError, BenchError};
+#[cfg(feature = "ffi")]
+pub use ffi::{rc_playground_entity_clone, rc_playground_entity_drop, ENTITY_OFFSET};
+pub use game::{
+    Archetypes, CloneDropSplit, Factory, Game, GameBuilder, ManualGame, MutOrRead, SoaGame,
+    UpdateGame, WeakGame,
+};
+pub use micro::run_micro_benchmarks;
+pub use named::Named;
+#[cfg(feature = "observer")]
+pub use observer::{set_observer, CountingObserver, RcObserver};
+#[cfg(feature = "race-demo")]
+pub use parallel::stress_arc_reads_racing;
+pub use parallel::{
+    bench_neighbours, bench_parallel, run_arc_stress, run_parallel_benchmark, stress_arc_reads,
+    ReaderChecksum,
+};
+pub use pool::PooledRc;
+pub use rc::{
+    Constructor, CountedRc, CustomRc, CustomWeak, MutableRc, ProjectedRc, RcLike, StdArcWrapper,
+    StdRcWrapper, WeakRc,
+};
+pub use registry::EntityRegistry;
+pub use report::{
+    median, median_relative_error, ns_per_operation, sample_until_stable, Matrix, MatrixRow,
+    RunStamp, StableSamples,
+};
+pub use rng::SplitMix64;
+pub use rss::{peak_rss, reset_peak_rss};
+pub use small::{SmallRc, INLINE_LIMIT};
+pub use storm::{run_upgrade_storm_benchmark, upgrade_storm, UpgradeStorm};
+pub use trace::Trace;
@@ -1,239 +1,165 @@
-use std::cell::{RefCell, UnsafeCell};
-use std::ops::Deref;
-use std::rc::Rc as StdRc;
-use std::time::{Duration, Instant};
-
-// ========================
-// 1. Define the Entity
-// ========================
-
-#[derive(Debug)]
-struct Entity {
-    id: usize,
-    x: f32,
-    y: f32,
-}
+use std::process;
+
+use rc_playground::{
+    check_clone_allocation_free, compare_setup_allocators, cross_check, fuzz_equivalence,
+    run_arc_stress, run_archetype_benchmark, run_clone_drop_benchmark, run_clone_tax_benchmark,
+    run_drop_order_benchmark, run_handoff_benchmark, run_micro_benchmarks,
+    run_mut_or_read_benchmark, run_parallel_benchmark, run_upgrade_storm_benchmark,
+    run_weak_game_benchmark, stress_scopes, BenchAllocator, BenchError, Bencher, Command, Config,
+    CustomRc, Entity, OutputFormat, RunStamp, StdRcWrapper, Trace,
+};
+
+#[global_allocator]
+static ALLOCATOR: BenchAllocator = BenchAllocator::new();
+
+fn run() -> Result<(), BenchError> {
+    let command = Command::from_args(std::env::args().skip(1))?;
+    let config = command.config();
+    print!(
+        "{}",
+        RunStamp::new(config, "BenchAllocator (over System)").render(config.format)
+    );
 
-impl Entity {
-    fn update(&mut self) {
-        // Simple update: move the entity
-        self.x += 1.0;
-        self.y += 1.0;
+    match command {
+        Command::Run(config) => run_config(config),
+        Command::Sweep(config) => {
+            check(&config)?;
+            let bencher = Bencher::new(config).allocator(&ALLOCATOR);
+            print!("{}", bencher.run_matrix()?.render(bencher.config().format));
+            Ok(())
+        }
+        Command::Profile {
+            config,
+            implementation,
+            duration,
+        } => {
+            // No checks and no warm-up: the profile should be the game only.
+            println!("Profiling {} for {:?}...", implementation.name(), duration);
+            let bencher = Bencher::new(config).warm_up(false);
+            let games = bencher.profile(implementation, duration)?;
+            println!("played {} games", games);
+            Ok(())
+        }
+        Command::Compare {
+            config,
+            baseline,
+            candidate,
+        } => {
+            check(&config)?;
+            Bencher::new(config)
+                .allocator(&ALLOCATOR)
+                .compare(baseline, candidate)
+                .map(drop)
+        }
     }
 }
 
-// ========================
-// 2. Define the RcLike Trait
-// ========================
-
-/// A trait that encapsulates the behaviors of a reference-counted smart pointer.
-/// It requires implementing `Clone` and `Deref` (and optionally `DerefMut`).
-trait RcLike<T>: Clone + Deref<Target = T> + Constructor<T> {}
-trait Constructor<T> {
-    fn new(value: T) -> Self;
-}
-
-// ========================
-// 3. Implement RcLike for StdRc
-// ========================
-
-struct StdRcWrapper<T>(StdRc<T>);
-
-impl<T> Clone for StdRcWrapper<T> {
-    fn clone(&self) -> Self {
-        StdRcWrapper(StdRc::clone(&self.0))
+/// Everything the binary did before it had subcommands.
+fn run_config(config: Config) -> Result<(), BenchError> {
+    if config.compare_allocators {
+        return compare_setup_allocators(&ALLOCATOR, &config);
     }
-}
-
-impl<T> Deref for StdRcWrapper<T> {
-    type Target = T;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    if config.micro {
+        return run_micro_benchmarks(&config);
     }
-}
 
-impl<T> Constructor<T> for StdRcWrapper<T> {
-    fn new(value: T) -> Self {
-        StdRcWrapper(StdRc::new(value))
+    if let Some(archetypes) = config.archetypes {
+        return run_archetype_benchmark(&config, archetypes);
     }
-}
-
-// ========================
-// 4. Implement a Simple CustomRc
-// ========================
 
-use std::ptr::NonNull;
-
-/// A simplified CustomRc implementation for benchmarking.
-/// Note: This implementation is not thread-safe and is for benchmarking purposes only.
-
-struct CustomRcInner<T> {
-    ref_count: UnsafeCell<usize>,
-    value: T,
-}
-
-pub struct CustomRc<T> {
-    ptr: NonNull<CustomRcInner<T>>,
-}
-
-impl<T> CustomRc<T> {
-    /// Creates a new CustomRc instance.
-    pub fn new(value: T) -> Self {
-        let boxed = Box::new(CustomRcInner {
-            ref_count: UnsafeCell::new(1),
-            value,
-        });
-        CustomRc {
-            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(boxed)) },
-        }
+    if config.clone_tax {
+        return run_clone_tax_benchmark(&config);
     }
 
-    /// Decrements the reference count and deallocates if it reaches zero.
-    fn drop_rc(&mut self) {
-        let inner = unsafe { self.ptr.as_mut() };
-        let count = unsafe { &mut *inner.ref_count.get() };
-        *count -= 1;
-        if *count == 0 {
-            unsafe {
-                let _ = Box::from_raw(self.ptr.as_ptr());
-            }
-        }
+    if config.clone_drop_split {
+        return run_clone_drop_benchmark(&config);
     }
-}
 
-impl<T> Clone for CustomRc<T> {
-    fn clone(&self) -> Self {
-        unsafe {
-            let inner = self.ptr.as_ref();
-            let old_count = *inner.ref_count.get();
-            // We know this is safe as long as we're single-threaded
-            let inner = &mut *self.ptr.as_ptr();
-            let count = &mut *inner.ref_count.get();
-            *count = old_count + 1;
-        }
-        CustomRc { ptr: self.ptr }
+    if config.weak_game {
+        return run_weak_game_benchmark(&config);
     }
-}
 
-impl<T> Deref for CustomRc<T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.ptr.as_ref().value }
+    if config.drop_order {
+        return run_drop_order_benchmark(&config);
     }
-}
 
-impl<T> Drop for CustomRc<T> {
-    fn drop(&mut self) {
-        self.drop_rc();
+    if config.handoff {
+        return run_handoff_benchmark(&ALLOCATOR, &config);
     }
-}
 
-impl Constructor<Entity> for CustomRc<Entity> {
-    fn new(value: Entity) -> Self {
-        CustomRc::new(value)
+    if let Some(shared_fraction) = config.mut_or_read {
+        return run_mut_or_read_benchmark(&config, shared_fraction);
     }
-}
 
-// ========================
-// 5. Define the Game Structure
-// ========================
-
-struct Game<RcType>
-where
-    RcType: RcLike<Entity>,
-{
-    entities: Vec<RcType>,
-    frames: usize,
-    operations_per_frame: usize,
-}
+    if let Some(despawn_fraction) = config.upgrade_storm {
+        return run_upgrade_storm_benchmark(&config, despawn_fraction);
+    }
 
-impl<RcType> Game<RcType>
-where
-    RcType: RcLike<Entity>,
-{
-    fn new(frames: usize, operations_per_frame: usize) -> Self {
-        Game {
-            entities: Vec::new(),
-            frames,
-            operations_per_frame,
-        }
+    if let Some(threads) = config.threads {
+        return run_parallel_benchmark(&config, threads);
     }
 
-    fn setup(&mut self, num_entities: usize) {
-        for id in 0..num_entities {
-            let entity = Entity { id, x: 0.0, y: 0.0 };
-            self.entities.push(RcType::clone(&RcType::new(entity)));
-        }
+    if let Some(threads) = config.stress_arc {
+        return run_arc_stress(&config, threads);
     }
 
-    fn run(&mut self) {
-        for frame in 0..self.frames {
-            for _ in 0..self.operations_per_frame {
-                // Iterate through entities and perform operations
-                for entity_rc in &self.entities {
-                    let cloned_rc = entity_rc.clone();
-                    let entity = cloned_rc.deref();
-                    // Perform some dummy calculations
-                    let _ = std::hint::black_box(entity.x + entity.y);
-                    // cloned_rc goes out of scope here
-                }
+    if let Some(steps) = config.fuzz_steps {
+        return match fuzz_equivalence::<StdRcWrapper<Entity>, CustomRc<Entity>>(config.seed, steps)
+        {
+            Ok(_) => {
+                println!(
+                    "StdRc and CustomRc agreed on {} operations (seed {})",
+                    steps, config.seed
+                );
+                Ok(())
             }
-            // Optionally, print progress
-            if frame % (self.frames / 10).max(1) == 0 {
-                println!("Completed frame {}/{}", frame, self.frames);
+            Err(log) => {
+                let path = format!("divergence-{}.oplog", config.seed);
+                log.save(&path)?;
+                Err(BenchError::Diverged(path))
             }
-        }
+        };
     }
-}
 
-impl<RcType> RcLike<Entity> for RcType where
-    RcType: Clone + Deref<Target = Entity> + Constructor<Entity>
-{
-}
+    if let Some(steps) = config.stress_steps {
+        let clones = stress_scopes::<CustomRc<Entity>>(config.seed, steps)?;
+        println!(
+            "CustomRc balanced {} clones across nested scopes (seed {})",
+            clones, config.seed
+        );
+        return Ok(());
+    }
 
-// ========================
-// 6. Benchmarking Function
-// ========================
-
-fn benchmark<RcType>(name: &str, frames: usize, operations_per_frame: usize, num_entities: usize)
-where
-    RcType: RcLike<Entity>,
-{
-    println!("Benchmarking {}...", name);
-    let start = Instant::now();
-    let mut game = Game::<RcType>::new(frames, operations_per_frame);
-    game.setup(num_entities);
-    game.run();
-    let duration = start.elapsed();
-    println!(
-        "{} completed in {:?} ({} frames, {} operations/frame)\n",
-        name, duration, frames, operations_per_frame
-    );
+    check(&config)?;
+
+    // The Bencher warms each implementation up right before timing it.
+    let bencher = Bencher::new(config).allocator(&ALLOCATOR);
+    let config = bencher.config();
+    if !config.sweep_entities.is_empty() || config.format == OutputFormat::Markdown {
+        print!("{}", bencher.run_matrix()?.render(config.format));
+    } else if config.cache_report {
+        bencher.cache_report()?;
+    } else {
+        let results = bencher.run_all()?;
+        if let Some(path) = &config.trace {
+            Trace::from_results(&results).save(path)?;
+            println!("trace written to {}", path);
+        }
+    }
+    Ok(())
 }
 
-// ========================
-// 7. Main Function
-// ========================
+/// Makes sure CustomRc behaves like Rc before timing anything, and that a
+/// clone really is nothing but a count bump.
+fn check(config: &Config) -> Result<(), BenchError> {
+    cross_check::<StdRcWrapper<Entity>, CustomRc<Entity>>(config)?;
+    check_clone_allocation_free(&ALLOCATOR)
+}
 
 fn main() {
-    // Configuration
-    let num_entities = 25_000; // Number of entities in the game
-    let num_frames = 25; // Number of frames to simulate
-    let operations_per_frame = 10_000; // Number of operations per frame
-
-    // Warm-up (optional)
-    println!("Warming up...");
-    {
-        let mut game = Game::<StdRcWrapper<Entity>>::new(num_frames, operations_per_frame);
-        game.setup(num_entities);
-        game.run();
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        process::exit(1);
     }
-    println!("Warm-up completed.\n");
-
-    // Benchmark StdRc
-    benchmark::<StdRcWrapper<Entity>>("StdRc", num_frames, operations_per_frame, num_entities);
-
-    // Benchmark CustomRc
-    benchmark::<CustomRc<Entity>>("CustomRc", num_frames, operations_per_frame, num_entities);
 }
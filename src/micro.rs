@@ -0,0 +1,671 @@
+use std::hint::black_box;
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::copy::CopyRc;
+use crate::entity::Entity;
+use crate::error::BenchError;
+use crate::game::GameBuilder;
+use crate::rc::{Constructor, CountedRc, CustomRc, RcLike, StdRcWrapper};
+use crate::rng::SplitMix64;
+use crate::small::{SmallRc, INLINE_LIMIT};
+
+// ========================
+// Clone Churn vs Move Churn
+// ========================
+
+/// Clones one handle and drops the clone immediately, `iterations` times.
+pub fn bench_clone_churn(iterations: usize) -> Duration {
+    let rc = CustomRc::new(Entity {
+        id: 0,
+        x: 0.0,
+        y: 0.0,
+    });
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let cloned_rc = black_box(rc.clone());
+        drop(cloned_rc);
+    }
+    start.elapsed()
+}
+
+// Each hop takes the handle by value and hands it back: a move, never a clone.
+#[inline(never)]
+fn hop_a(rc: CustomRc<Entity>) -> CustomRc<Entity> {
+    black_box(rc)
+}
+
+#[inline(never)]
+fn hop_b(rc: CustomRc<Entity>) -> CustomRc<Entity> {
+    hop_a(black_box(rc))
+}
+
+#[inline(never)]
+fn hop_c(rc: CustomRc<Entity>) -> CustomRc<Entity> {
+    hop_b(black_box(rc))
+}
+
+/// Moves one handle through a chain of functions `iterations` times. Moving
+/// must never touch the count, so it's checked to still be one afterwards.
+pub fn bench_move_churn(iterations: usize) -> Result<Duration, BenchError> {
+    let mut rc = CustomRc::new(Entity {
+        id: 0,
+        x: 0.0,
+        y: 0.0,
+    });
+    let start = Instant::now();
+    for _ in 0..iterations {
+        rc = hop_c(rc);
+    }
+    let duration = start.elapsed();
+    let count = CustomRc::strong_count(&rc);
+    if count != 1 {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "moving a CustomRc left its strong count at {}",
+            count
+        )));
+    }
+    Ok(duration)
+}
+
+// ========================
+// Share Then Release
+// ========================
+
+/// Phase timings of `bench_share_release`, summed over all rounds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShareReleaseTiming {
+    pub clone_in: Duration,
+    pub process: Duration,
+    pub bulk_drop: Duration,
+}
+
+/// Models an event queue: each round clones `events` handles (cycling over
+/// `num_entities` entities) into a queue, reads every queued event, then
+/// clears the queue, dropping all the clones at once.
+pub fn bench_share_release<RcType>(
+    num_entities: usize,
+    events: usize,
+    rounds: usize,
+) -> ShareReleaseTiming
+where
+    RcType: RcLike<Entity>,
+{
+    let entities: Vec<RcType> = (0..num_entities)
+        .map(|id| RcType::new(Entity { id, x: 0.0, y: 0.0 }))
+        .collect();
+    let mut queue: Vec<RcType> = Vec::with_capacity(events);
+    let mut timing = ShareReleaseTiming::default();
+    for _ in 0..rounds {
+        let start = Instant::now();
+        for entity_rc in entities.iter().cycle().take(events) {
+            queue.push(entity_rc.clone());
+        }
+        let cloned = Instant::now();
+        for event in &queue {
+            black_box(event.x + event.y);
+        }
+        let processed = Instant::now();
+        queue.clear();
+        let cleared = Instant::now();
+        timing.clone_in += cloned - start;
+        timing.process += processed - cloned;
+        timing.bulk_drop += cleared - processed;
+    }
+    timing
+}
+
+// ========================
+// Clone Cost With and Without black_box
+// ========================
+
+/// Timings of the same clone-read-drop loop with decreasing room for the
+/// optimizer; see `bench_black_box`.
+#[derive(Debug, Clone, Copy)]
+pub struct BlackBoxTiming {
+    /// Nothing is fed through `black_box`.
+    pub bare: Duration,
+    /// Only the read (`x + y`) is fed through `black_box`, like `Game::run`.
+    pub read_boxed: Duration,
+    /// The clone itself and the read both go through `black_box`.
+    pub clone_boxed: Duration,
+}
+
+/// Runs the clone-read-drop loop three ways to show how much of it the
+/// optimizer removes.
+///
+/// Observed in release builds (x86_64): `CustomRc`'s `bare` loop is deleted
+/// outright (0.00 ns/iter), while `StdRc`'s survives only partially because
+/// its overflow check keeps the increment alive. Boxing the read puts both at
+/// the cost `Game::run` measures, so that loop is not being elided. Boxing the
+/// clone as well adds a further ~25% for `CustomRc`, which is the cost of
+/// forcing the handle through memory rather than extra count traffic.
+pub fn bench_black_box<RcType>(iterations: usize) -> BlackBoxTiming
+where
+    RcType: RcLike<Entity>,
+{
+    let rc = RcType::new(Entity {
+        id: 0,
+        x: 1.0,
+        y: 2.0,
+    });
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let cloned_rc = rc.clone();
+        let _ = cloned_rc.x + cloned_rc.y;
+    }
+    let bare = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let cloned_rc = rc.clone();
+        black_box(cloned_rc.x + cloned_rc.y);
+    }
+    let read_boxed = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let cloned_rc = black_box(rc.clone());
+        black_box(cloned_rc.x + cloned_rc.y);
+    }
+    let clone_boxed = start.elapsed();
+
+    BlackBoxTiming {
+        bare,
+        read_boxed,
+        clone_boxed,
+    }
+}
+
+// ========================
+// Free vs Decrement
+// ========================
+
+/// Time to drop `drops` handles that were each the last one to their
+/// entity, and as many that each had a clone left.
+#[derive(Debug, Clone, Copy)]
+pub struct DropTailTiming {
+    pub drops: usize,
+    pub freeing: Duration,
+    pub decrementing: Duration,
+    /// With a `config.retain_rate`, the same drops when only that share of
+    /// the entities has a clone left.
+    pub retaining: Option<Duration>,
+}
+
+/// Sets up two games of `config.num_entities` entities, one with a retained
+/// clone per entity, and times dropping every entity's own handle in each.
+/// The entity vector is reserved up front and keeps its buffer, so the
+/// difference is what the allocator's free costs on top of the decrement.
+/// With a `config.retain_rate`, a third game keeps a clone of only that
+/// share of the entities.
+pub fn bench_drop_tail<RcType>(config: &Config) -> Result<DropTailTiming, BenchError>
+where
+    RcType: CountedRc<Entity>,
+{
+    let time_drops = |initial_clones_per_entity, retain_rate| {
+        let mut game = GameBuilder::from_config(config)
+            .quiet(true)
+            .initial_clones_per_entity(initial_clones_per_entity)
+            .retain_rate(retain_rate)
+            .build::<RcType>()?;
+        let start = Instant::now();
+        game.drop_entities();
+        let duration = start.elapsed();
+        // The retained clones, if any, are freed here, untimed.
+        drop(game);
+        Ok::<_, BenchError>(duration)
+    };
+    Ok(DropTailTiming {
+        drops: config.num_entities,
+        freeing: time_drops(0, 0.0)?,
+        decrementing: time_drops(1, 0.0)?,
+        retaining: if config.retain_rate > 0.0 {
+            Some(time_drops(0, config.retain_rate)?)
+        } else {
+            None
+        },
+    })
+}
+
+// ========================
+// Predictable vs Unpredictable Frees
+// ========================
+
+/// Percentages of freeing drops `run_micro_benchmarks` tries.
+pub const FREE_PERCENTAGES: [u32; 7] = [0, 10, 25, 50, 75, 90, 100];
+
+/// Time to drop `drops` handles of which `free_percent` percent, at random,
+/// were the last to their entity.
+#[derive(Debug, Clone, Copy)]
+pub struct FreeShareTiming {
+    pub free_percent: u32,
+    pub drops: usize,
+    pub duration: Duration,
+}
+
+/// For each of `rounds` rounds, creates `num_entities` handles and keeps a
+/// clone of each one with probability `1 - free_percent / 100`, then times
+/// dropping the handles in creation order. Whether a drop frees is decided
+/// per handle by `seed`, so at 0% and 100% the branch in `drop_rc` always
+/// goes the same way, and at 50% it's a coin flip the predictor can't learn.
+/// The clones are dropped afterwards, untimed.
+pub fn bench_free_share<RcType>(
+    num_entities: usize,
+    rounds: usize,
+    free_percent: u32,
+    seed: u64,
+) -> FreeShareTiming
+where
+    RcType: RcLike<Entity>,
+{
+    let mut rng = SplitMix64::new(seed);
+    let mut handles: Vec<RcType> = Vec::with_capacity(num_entities);
+    let mut retained: Vec<RcType> = Vec::with_capacity(num_entities);
+    let mut duration = Duration::ZERO;
+    for _ in 0..rounds {
+        for id in 0..num_entities {
+            let entity_rc = RcType::new(Entity { id, x: 0.0, y: 0.0 });
+            if rng.below(100) as u32 >= free_percent {
+                retained.push(entity_rc.clone());
+            }
+            handles.push(entity_rc);
+        }
+        let start = Instant::now();
+        handles.clear();
+        duration += start.elapsed();
+        retained.clear();
+    }
+    FreeShareTiming {
+        free_percent,
+        drops: num_entities * rounds,
+        duration,
+    }
+}
+
+/// How much slower each timing is per drop than the straight line between
+/// the 0% and 100% ones predicts: the part of the cost that is branch
+/// misprediction rather than the frees themselves. Needs both ends.
+pub fn misprediction_excess(timings: &[FreeShareTiming]) -> Option<Vec<f64>> {
+    let per_drop = |timing: &FreeShareTiming| per_iteration(timing.duration, timing.drops);
+    let never = per_drop(timings.iter().find(|timing| timing.free_percent == 0)?);
+    let always = per_drop(timings.iter().find(|timing| timing.free_percent == 100)?);
+    Some(
+        timings
+            .iter()
+            .map(|timing| {
+                let share = f64::from(timing.free_percent) / 100.0;
+                per_drop(timing) - (never + share * (always - never))
+            })
+            .collect(),
+    )
+}
+
+// ========================
+// Share or Copy
+// ========================
+
+/// Clone-read-drop time of a shared (`CustomRc`) and a copied (`CopyRc`)
+/// payload of `bytes` bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct PayloadTiming {
+    pub bytes: usize,
+    pub shared: Duration,
+    pub copied: Duration,
+}
+
+/// Most iterations `run_micro_benchmarks` spends on each payload size: a
+/// copied 4 KiB payload is slow enough that the full count takes minutes.
+pub const MAX_PAYLOAD_ITERATIONS: usize = 1_000_000;
+
+/// `bench_payload` at every power of two from 8 bytes to 4 KiB.
+pub fn bench_payload_sizes(iterations: usize) -> Vec<PayloadTiming> {
+    vec![
+        bench_payload::<8>(iterations),
+        bench_payload::<16>(iterations),
+        bench_payload::<32>(iterations),
+        bench_payload::<64>(iterations),
+        bench_payload::<128>(iterations),
+        bench_payload::<256>(iterations),
+        bench_payload::<512>(iterations),
+        bench_payload::<1024>(iterations),
+        bench_payload::<2048>(iterations),
+        bench_payload::<4096>(iterations),
+    ]
+}
+
+/// Clones, reads and drops an `N`-byte payload `iterations` times, shared
+/// and then copied.
+pub fn bench_payload<const N: usize>(iterations: usize) -> PayloadTiming {
+    PayloadTiming {
+        bytes: N,
+        shared: clone_read_drop::<CustomRc<[u8; N]>, N>(iterations),
+        copied: clone_read_drop::<CopyRc<[u8; N]>, N>(iterations),
+    }
+}
+
+fn clone_read_drop<RcType, const N: usize>(iterations: usize) -> Duration
+where
+    RcType: Clone + Deref<Target = [u8; N]> + Constructor<[u8; N]>,
+{
+    clone_read_drop_of(RcType::new([1; N]), iterations)
+}
+
+fn clone_read_drop_of<RcType, const N: usize>(rc: RcType, iterations: usize) -> Duration
+where
+    RcType: Clone + Deref<Target = [u8; N]>,
+{
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let cloned_rc = black_box(rc.clone());
+        black_box(cloned_rc[N - 1]);
+    }
+    start.elapsed()
+}
+
+/// The smallest payload size from which sharing beats copying at every
+/// larger size too, or `None` if copying still wins at the largest.
+pub fn sharing_crossover(timings: &[PayloadTiming]) -> Option<usize> {
+    let mut crossover = None;
+    for timing in timings {
+        if timing.shared < timing.copied {
+            crossover.get_or_insert(timing.bytes);
+        } else {
+            crossover = None;
+        }
+    }
+    crossover
+}
+
+// ========================
+// Inline or Share
+// ========================
+
+/// Per-iteration time of `SmallRc`'s two paths for a payload of `bytes`
+/// bytes: the value behind a `CustomRc` and the value inline.
+#[derive(Debug, Clone, Copy)]
+pub struct InlineTiming {
+    pub bytes: usize,
+    /// Clone-read-drop of a long-lived handle.
+    pub shared_clone: Duration,
+    pub inline_clone: Duration,
+    /// Create-read-drop of a new handle.
+    pub shared_spawn: Duration,
+    pub inline_spawn: Duration,
+}
+
+impl InlineTiming {
+    /// Clones per spawn at which inlining stops paying: what each spawn
+    /// saves over the clones' extra cost. `None` if inlining wins (or
+    /// loses) on both paths, so no number of clones changes the outcome.
+    pub fn break_even_clones(&self) -> Option<f64> {
+        let saved = self.shared_spawn.as_secs_f64() - self.inline_spawn.as_secs_f64();
+        let lost = self.inline_clone.as_secs_f64() - self.shared_clone.as_secs_f64();
+        (saved > 0.0 && lost > 0.0).then(|| saved / lost)
+    }
+}
+
+/// `bench_inline` at every power of two from 8 bytes to 4 KiB.
+pub fn bench_inline_sizes(iterations: usize) -> Vec<InlineTiming> {
+    vec![
+        bench_inline::<8>(iterations),
+        bench_inline::<16>(iterations),
+        bench_inline::<32>(iterations),
+        bench_inline::<64>(iterations),
+        bench_inline::<128>(iterations),
+        bench_inline::<256>(iterations),
+        bench_inline::<512>(iterations),
+        bench_inline::<1024>(iterations),
+        bench_inline::<2048>(iterations),
+        bench_inline::<4096>(iterations),
+    ]
+}
+
+/// Clones and then spawns an `N`-byte payload `iterations` times each,
+/// shared and inline.
+pub fn bench_inline<const N: usize>(iterations: usize) -> InlineTiming {
+    InlineTiming {
+        bytes: N,
+        shared_clone: clone_read_drop::<CustomRc<[u8; N]>, N>(iterations),
+        inline_clone: clone_read_drop_of(SmallRc::inline([1; N]), iterations),
+        shared_spawn: spawn_read_drop(|| CustomRc::new([1; N]), iterations),
+        inline_spawn: spawn_read_drop(|| SmallRc::inline([1; N]), iterations),
+    }
+}
+
+fn spawn_read_drop<RcType, const N: usize>(
+    spawn: impl Fn() -> RcType,
+    iterations: usize,
+) -> Duration
+where
+    RcType: Deref<Target = [u8; N]>,
+{
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let rc = black_box(spawn());
+        black_box(rc[N - 1]);
+    }
+    start.elapsed()
+}
+
+/// The largest payload size up to which the inline path beats the shared
+/// one at every smaller size too, by `cost`, or `None` if sharing already
+/// wins at the smallest.
+pub fn inline_crossover(
+    timings: &[InlineTiming],
+    cost: impl Fn(&InlineTiming) -> (Duration, Duration),
+) -> Option<usize> {
+    timings
+        .iter()
+        .take_while(|timing| {
+            let (shared, inline) = cost(timing);
+            inline < shared
+        })
+        .last()
+        .map(|timing| timing.bytes)
+}
+
+// ========================
+// Run the Micro-benchmarks
+// ========================
+
+fn per_iteration(duration: Duration, iterations: usize) -> f64 {
+    duration.as_nanos() as f64 / iterations.max(1) as f64
+}
+
+/// Runs every micro-benchmark with `entities * operations_per_frame`
+/// iterations and prints the results.
+pub fn run_micro_benchmarks(config: &Config) -> Result<(), BenchError> {
+    let iterations = config
+        .num_entities
+        .checked_mul(config.operations_per_frame)
+        .ok_or(BenchError::Overflow)?;
+    println!("Micro-benchmarks ({} iterations each)...", iterations);
+
+    let clone = bench_clone_churn(iterations);
+    let moved = bench_move_churn(iterations)?;
+    println!(
+        "clone churn: {:?} ({:.2} ns/iter)",
+        clone,
+        per_iteration(clone, iterations)
+    );
+    println!(
+        "move churn:  {:?} ({:.2} ns/iter)",
+        moved,
+        per_iteration(moved, iterations)
+    );
+    println!(
+        "move/clone:  {:.2}\n",
+        moved.as_secs_f64() / clone.as_secs_f64()
+    );
+
+    println!(
+        "Share then release ({} events/round, {} rounds):",
+        config.operations_per_frame, config.num_frames
+    );
+    for (name, timing) in [
+        (
+            "StdRc",
+            bench_share_release::<StdRcWrapper<Entity>>(
+                config.num_entities,
+                config.operations_per_frame,
+                config.num_frames,
+            ),
+        ),
+        (
+            "CustomRc",
+            bench_share_release::<CustomRc<Entity>>(
+                config.num_entities,
+                config.operations_per_frame,
+                config.num_frames,
+            ),
+        ),
+    ] {
+        println!(
+            "{:<10} clone-in {:?}, process {:?}, bulk drop {:?}",
+            name, timing.clone_in, timing.process, timing.bulk_drop
+        );
+    }
+    println!();
+
+    println!("Clone cost with and without black_box (ns/iter):");
+    for (name, timing) in [
+        ("StdRc", bench_black_box::<StdRcWrapper<Entity>>(iterations)),
+        ("CustomRc", bench_black_box::<CustomRc<Entity>>(iterations)),
+    ] {
+        println!(
+            "{:<10} bare {:.2}, read boxed {:.2}, clone boxed {:.2}",
+            name,
+            per_iteration(timing.bare, iterations),
+            per_iteration(timing.read_boxed, iterations),
+            per_iteration(timing.clone_boxed, iterations)
+        );
+    }
+    println!();
+
+    let tail = bench_drop_tail::<CustomRc<Entity>>(config)?;
+    let (freeing, decrementing) = (
+        per_iteration(tail.freeing, tail.drops),
+        per_iteration(tail.decrementing, tail.drops),
+    );
+    println!("CustomRc drop tail ({} drops, ns/drop):", tail.drops);
+    println!(
+        "last handle (frees) {:.2}, shared handle (decrements) {:.2}, free costs {:.2}",
+        freeing,
+        decrementing,
+        freeing - decrementing
+    );
+    if let Some(retaining) = tail.retaining {
+        println!(
+            "retain rate {:.0}% (the rest free): {:.2}",
+            config.retain_rate * 100.0,
+            per_iteration(retaining, tail.drops)
+        );
+    }
+    println!();
+
+    println!(
+        "CustomRc drops by share that free ({} drops each, ns/drop):",
+        config.num_entities * config.num_frames
+    );
+    println!("{:<10} {:>12} {:>14}", "frees", "ns/drop", "over linear");
+    let timings: Vec<FreeShareTiming> = FREE_PERCENTAGES
+        .iter()
+        .map(|&percent| {
+            bench_free_share::<CustomRc<Entity>>(
+                config.num_entities,
+                config.num_frames,
+                percent,
+                config.seed,
+            )
+        })
+        .collect();
+    let excess = misprediction_excess(&timings).unwrap_or_default();
+    for (index, timing) in timings.iter().enumerate() {
+        println!(
+            "{:<10} {:>12.2} {:>14.2}",
+            format!("{}%", timing.free_percent),
+            per_iteration(timing.duration, timing.drops),
+            excess.get(index).copied().unwrap_or_default()
+        );
+    }
+    println!();
+
+    let payload_iterations = iterations.min(MAX_PAYLOAD_ITERATIONS);
+    println!(
+        "Share or copy, clone-read-drop ({} iterations, ns/iter):",
+        payload_iterations
+    );
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "bytes", "CustomRc", "CopyRc", "copy/share"
+    );
+    let timings = bench_payload_sizes(payload_iterations);
+    for timing in &timings {
+        println!(
+            "{:<10} {:>12.2} {:>12.2} {:>12.2}",
+            timing.bytes,
+            per_iteration(timing.shared, payload_iterations),
+            per_iteration(timing.copied, payload_iterations),
+            timing.copied.as_secs_f64() / timing.shared.as_secs_f64()
+        );
+    }
+    match sharing_crossover(&timings) {
+        Some(bytes) => println!("crossover: sharing pays off from {} bytes", bytes),
+        None => println!("crossover: copying still wins at the largest payload"),
+    }
+    println!();
+
+    println!(
+        "Inline or share, clone-read-drop and create-read-drop ({} iterations, ns/iter):",
+        payload_iterations
+    );
+    println!(
+        "{:<10} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "bytes", "clone Rc", "clone inline", "spawn Rc", "spawn inline", "break-even"
+    );
+    let timings = bench_inline_sizes(payload_iterations);
+    for timing in &timings {
+        println!(
+            "{:<10} {:>12.2} {:>12.2} {:>12.2} {:>12.2} {:>12}",
+            timing.bytes,
+            per_iteration(timing.shared_clone, payload_iterations),
+            per_iteration(timing.inline_clone, payload_iterations),
+            per_iteration(timing.shared_spawn, payload_iterations),
+            per_iteration(timing.inline_spawn, payload_iterations),
+            timing
+                .break_even_clones()
+                .map_or_else(|| "-".to_string(), |clones| format!("{:.1}", clones))
+        );
+    }
+    for (path, crossover) in [
+        (
+            "clone",
+            inline_crossover(&timings, |timing| {
+                (timing.shared_clone, timing.inline_clone)
+            }),
+        ),
+        (
+            "spawn",
+            inline_crossover(&timings, |timing| {
+                (timing.shared_spawn, timing.inline_spawn)
+            }),
+        ),
+    ] {
+        match crossover {
+            Some(bytes) => println!(
+                "{} crossover: inlining pays off up to {} bytes",
+                path, bytes
+            ),
+            None => println!(
+                "{} crossover: sharing wins even at the smallest payload",
+                path
+            ),
+        }
+    }
+    println!("(SmallRc inlines up to {} bytes)", INLINE_LIMIT);
+    println!();
+    Ok(())
+}
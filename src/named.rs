@@ -0,0 +1,41 @@
+use std::ops::Deref;
+
+use crate::entity::{Entity, NamedEntity};
+use crate::rc::{Constructor, CountedRc};
+
+// ========================
+// Implement Named Handles
+// ========================
+
+/// A `P` pointing to a `NamedEntity`, looked at as a handle to its `Entity`,
+/// so any `Game` can be played with a payload that has a real `Drop`. Only
+/// the last drop differs from `P` to an `Entity`: it also frees the name.
+pub struct Named<P>(P);
+
+impl<P: Clone> Clone for Named<P> {
+    fn clone(&self) -> Self {
+        Named(self.0.clone())
+    }
+}
+
+impl<P: Deref<Target = NamedEntity>> Deref for Named<P> {
+    type Target = Entity;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.entity
+    }
+}
+
+impl<P: Constructor<NamedEntity>> Constructor<Entity> for Named<P> {
+    fn new(value: Entity) -> Self {
+        Named(P::new(NamedEntity::new(value)))
+    }
+}
+
+impl<P: CountedRc<NamedEntity>> CountedRc<Entity> for Named<P> {
+    const SHARED: bool = P::SHARED;
+
+    fn strong_count(&self) -> usize {
+        self.0.strong_count()
+    }
+}
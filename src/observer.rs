@@ -0,0 +1,98 @@
+//! Instrumentation hooks for `CustomRc`, only compiled in with the
+//! `observer` feature so the default build pays nothing for them.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Receives `CustomRc` lifecycle events on the thread it's installed on.
+/// Every method defaults to a no-op; `ptr` identifies the allocation.
+pub trait RcObserver {
+    /// A new allocation was created with one strong handle.
+    fn on_construct(&self, _ptr: *const ()) {}
+    /// A strong handle was cloned (or upgraded from a weak).
+    fn on_clone(&self, _ptr: *const ()) {}
+    /// A strong handle was dropped.
+    fn on_drop(&self, _ptr: *const ()) {}
+    /// The last strong handle went away, so the value was dropped or moved out.
+    fn on_free(&self, _ptr: *const ()) {}
+}
+
+thread_local! {
+    static OBSERVER: RefCell<Option<Rc<dyn RcObserver>>> = const { RefCell::new(None) };
+}
+
+/// Installs `observer` for the calling thread (or removes it with `None`),
+/// returning the previously installed one.
+pub fn set_observer(observer: Option<Rc<dyn RcObserver>>) -> Option<Rc<dyn RcObserver>> {
+    OBSERVER.with(|current| current.replace(observer))
+}
+
+/// Calls `f` with the installed observer, if any.
+pub(crate) fn notify(f: impl FnOnce(&dyn RcObserver)) {
+    // Clone it out first so the observer may itself create or drop handles.
+    if let Some(observer) = OBSERVER.with(|current| current.borrow().clone()) {
+        f(&*observer);
+    }
+}
+
+/// An observer that just counts each kind of event.
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    pub constructs: Cell<usize>,
+    pub clones: Cell<usize>,
+    pub drops: Cell<usize>,
+    pub frees: Cell<usize>,
+}
+
+fn bump(counter: &Cell<usize>) {
+    counter.set(counter.get() + 1);
+}
+
+impl RcObserver for CountingObserver {
+    fn on_construct(&self, _ptr: *const ()) {
+        bump(&self.constructs);
+    }
+
+    fn on_clone(&self, _ptr: *const ()) {
+        bump(&self.clones);
+    }
+
+    fn on_drop(&self, _ptr: *const ()) {
+        bump(&self.drops);
+    }
+
+    fn on_free(&self, _ptr: *const ()) {
+        bump(&self.frees);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc::CustomRc;
+
+    #[test]
+    fn the_installed_observer_sees_every_event() {
+        let observer = Rc::new(CountingObserver::default());
+        let previous = set_observer(Some(observer.clone()));
+        {
+            let rc = CustomRc::new(1);
+            drop(rc.clone());
+            let weak = CustomRc::downgrade(&rc);
+            drop(weak.upgrade());
+            drop(rc);
+            assert!(weak.upgrade().is_none());
+            let _ = CustomRc::try_unwrap(CustomRc::new(2));
+        }
+        set_observer(previous);
+
+        assert_eq!(observer.constructs.get(), 2);
+        assert_eq!(observer.clones.get(), 2);
+        assert_eq!(observer.drops.get(), 4);
+        assert_eq!(observer.frees.get(), observer.constructs.get());
+        assert_eq!(
+            observer.constructs.get() + observer.clones.get(),
+            observer.drops.get()
+        );
+    }
+}
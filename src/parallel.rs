@@ -0,0 +1,423 @@
+use std::hint::black_box;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::arc::{CustomArc, PaddedArc};
+use crate::bench::throughput;
+use crate::config::{total_operations, Config};
+use crate::entity::Entity;
+use crate::error::BenchError;
+use crate::rc::{CountedRc, RcLike};
+
+// ========================
+// Pin Threads to Cores
+// ========================
+
+/// Restricts the calling thread to `core`.
+#[cfg(feature = "affinity")]
+fn pin_to_core(core: usize) -> Result<(), BenchError> {
+    // `cpu_set_t` from glibc: a 1024-bit mask.
+    const CPU_SET_WORDS: usize = 1024 / u64::BITS as usize;
+
+    extern "C" {
+        fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+    }
+
+    if core >= CPU_SET_WORDS * u64::BITS as usize {
+        return Err(BenchError::InvalidArgument(format!("core {}", core)));
+    }
+    let mut mask = [0u64; CPU_SET_WORDS];
+    mask[core / u64::BITS as usize] |= 1 << (core % u64::BITS as usize);
+    // pid 0 is the calling thread.
+    let result = unsafe { sched_setaffinity(0, std::mem::size_of_val(&mask), mask.as_ptr()) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "affinity"))]
+fn pin_to_core(_core: usize) -> Result<(), BenchError> {
+    Err(BenchError::InvalidArgument(
+        "--pin-threads (build with the `affinity` feature)".to_string(),
+    ))
+}
+
+// ========================
+// Parallel Clone Benchmark
+// ========================
+
+/// How long one thread spent on its share of the clones.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadTiming {
+    /// The core the thread was pinned to, if any.
+    pub core: Option<usize>,
+    pub duration: Duration,
+}
+
+/// Result of `bench_parallel`: one timing per thread plus the wall-clock time
+/// from the moment they were released together until the last one finished.
+#[derive(Debug, Clone)]
+pub struct ParallelTiming {
+    pub threads: Vec<ThreadTiming>,
+    pub wall: Duration,
+}
+
+/// Has `threads` threads clone, read and drop one shared handle
+/// `operations_per_thread` times each, all contending on the same count.
+pub fn bench_parallel<RcType>(
+    threads: usize,
+    operations_per_thread: usize,
+    pin: bool,
+) -> Result<ParallelTiming, BenchError>
+where
+    RcType: RcLike<Entity> + Send + Sync,
+{
+    let shared = RcType::new(entity(0));
+    let handles = (0..threads).map(|_| shared.clone()).collect();
+    run_threads(handles, operations_per_thread, pin)
+}
+
+/// Like `bench_parallel`, but every thread has a handle of its own,
+/// allocated back to back so that unpadded neighbours likely share a cache
+/// line. Nothing is logically shared, so any slowdown against
+/// `bench_parallel` with one thread is false sharing.
+pub fn bench_neighbours<RcType>(
+    threads: usize,
+    operations_per_thread: usize,
+    pin: bool,
+) -> Result<ParallelTiming, BenchError>
+where
+    RcType: RcLike<Entity> + Send + Sync,
+{
+    let handles = (0..threads).map(|id| RcType::new(entity(id))).collect();
+    run_threads(handles, operations_per_thread, pin)
+}
+
+fn entity(id: usize) -> Entity {
+    Entity { id, x: 1.0, y: 2.0 }
+}
+
+/// Moves one handle into each of `handles.len()` threads, which then clone,
+/// read and drop it `operations_per_thread` times.
+///
+/// Every thread waits on a barrier before starting its clock, so none of them
+/// gets a head start while the others are still being spawned (or pinned).
+fn run_threads<RcType>(
+    handles: Vec<RcType>,
+    operations_per_thread: usize,
+    pin: bool,
+) -> Result<ParallelTiming, BenchError>
+where
+    RcType: RcLike<Entity> + Send + Sync,
+{
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    // The extra party is this thread, which times the whole run.
+    let barrier = Barrier::new(handles.len() + 1);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = handles
+            .into_iter()
+            .enumerate()
+            .map(|(index, handle)| {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    let core = pin.then_some(index % cores);
+                    // Still reach the barrier on failure, or everyone hangs.
+                    let pinned = core.map_or(Ok(()), pin_to_core);
+                    barrier.wait();
+                    pinned?;
+                    let start = Instant::now();
+                    for _ in 0..operations_per_thread {
+                        let cloned_rc = handle.clone();
+                        black_box(cloned_rc.x + cloned_rc.y);
+                    }
+                    Ok(ThreadTiming {
+                        core,
+                        duration: start.elapsed(),
+                    })
+                })
+            })
+            .collect();
+
+        barrier.wait();
+        let start = Instant::now();
+        let threads = workers
+            .into_iter()
+            .map(|worker| worker.join().expect("benchmark thread panicked"))
+            .collect::<Result<_, BenchError>>()?;
+        Ok(ParallelTiming {
+            threads,
+            wall: start.elapsed(),
+        })
+    })
+}
+
+/// Runs `bench_parallel` for `CustomArc` with `threads` threads doing
+/// `frames * operations_per_frame` operations each, and prints aggregate and
+/// per-thread throughput. Then compares `bench_neighbours` for `CustomArc`
+/// and `PaddedArc` to put a number on false sharing.
+pub fn run_parallel_benchmark(config: &Config, threads: usize) -> Result<(), BenchError> {
+    let operations_per_thread =
+        total_operations(config.num_frames, config.operations_per_frame, 1)?;
+    let total = operations_per_thread
+        .checked_mul(threads)
+        .ok_or(BenchError::Overflow)?;
+
+    println!(
+        "Benchmarking CustomArc on {} threads{} ({} operations each)...",
+        threads,
+        if config.pin_threads { ", pinned" } else { "" },
+        operations_per_thread
+    );
+    let timing =
+        bench_parallel::<CustomArc<Entity>>(threads, operations_per_thread, config.pin_threads)?;
+    println!(
+        "aggregate: {:.2} Mops/s over {:?}",
+        throughput(total, timing.wall),
+        timing.wall
+    );
+    for (index, thread) in timing.threads.iter().enumerate() {
+        let core = thread
+            .core
+            .map_or_else(|| "unpinned".to_string(), |core| format!("core {}", core));
+        println!(
+            "thread {:<3} ({}): {:.2} Mops/s over {:?}",
+            index,
+            core,
+            throughput(operations_per_thread, thread.duration),
+            thread.duration
+        );
+    }
+    println!();
+
+    println!("Neighbouring handles, one per thread (false sharing):");
+    let unpadded =
+        bench_neighbours::<CustomArc<Entity>>(threads, operations_per_thread, config.pin_threads)?;
+    let padded =
+        bench_neighbours::<PaddedArc<Entity>>(threads, operations_per_thread, config.pin_threads)?;
+    let (unpadded_rate, padded_rate) = (
+        throughput(total, unpadded.wall),
+        throughput(total, padded.wall),
+    );
+    println!("CustomArc  {:.2} Mops/s", unpadded_rate);
+    println!(
+        "PaddedArc  {:.2} Mops/s ({:.2}x)",
+        padded_rate,
+        padded_rate / unpadded_rate
+    );
+    println!();
+    Ok(())
+}
+
+// ========================
+// Stress CustomArc Reads
+// ========================
+
+/// The two states the racing writer of `stress_arc_reads_racing` flips the entity
+/// between. Both sum to the same, while a read that sees half of each
+/// doesn't.
+const STRESSED: [(f32, f32); 2] = [(1.0, 2.0), (2.0, 1.0)];
+
+/// What one reader of `stress_arc_reads` saw.
+#[derive(Debug, Clone, Copy)]
+pub struct ReaderChecksum {
+    pub reads: usize,
+    /// Sum of `x + y` over every read.
+    pub checksum: f64,
+    /// Reads whose `(x, y)` was neither of the `STRESSED` states.
+    pub torn: usize,
+}
+
+/// Has `threads` threads clone one shared `CustomArc`, read both of its
+/// coordinates and drop the clone `reads_per_thread` times each, summing
+/// what they saw. Every reader must end with `reads * 3` and no torn reads,
+/// and the shared handle must be back to a count of 1.
+///
+/// Meant to run under ThreadSanitizer as well:
+///
+/// ```text
+/// RUSTFLAGS="-Zsanitizer=thread" cargo +nightly run -Zbuild-std \
+///     --target x86_64-unknown-linux-gnu -- --stress-arc 4 --frames 1
+/// ```
+///
+/// which must stay silent. Without `rust-src` for `-Zbuild-std`, adding
+/// `-Cunsafe-allow-abi-mismatch=sanitizer` to the flags also works, at the
+/// price of false positives inside the uninstrumented std.
+pub fn stress_arc_reads(
+    threads: usize,
+    reads_per_thread: usize,
+) -> Result<Vec<ReaderChecksum>, BenchError> {
+    // SAFETY: no racing writer, so every access is a read through the
+    // `CustomArc`.
+    unsafe { stress_reads(threads, reads_per_thread, false) }
+}
+
+/// `stress_arc_reads` while one more thread keeps rewriting the coordinates
+/// through the shared handle, one at a time, which is exactly the data race
+/// `Sync` forbids: it exists to show the harness catches one, by checksum or
+/// by ThreadSanitizer, which must report the writer when run as for
+/// `stress_arc_reads` with `--features race-demo -- --stress-arc-race`. A
+/// pass only catches the races that actually overlapped, so run it a few
+/// times.
+///
+/// # Safety
+///
+/// Calling this is always undefined behaviour. Only do so in a build whose
+/// point is to have a race detector report it.
+#[cfg(feature = "race-demo")]
+pub unsafe fn stress_arc_reads_racing(
+    threads: usize,
+    reads_per_thread: usize,
+) -> Result<Vec<ReaderChecksum>, BenchError> {
+    stress_reads(threads, reads_per_thread, true)
+}
+
+/// Runs the stress readers, and with `race` the racing writer too.
+///
+/// # Safety
+///
+/// `race` must be `false`, unless the caller accepts the data race.
+unsafe fn stress_reads(
+    threads: usize,
+    reads_per_thread: usize,
+    race: bool,
+) -> Result<Vec<ReaderChecksum>, BenchError> {
+    let (x, y) = STRESSED[0];
+    let shared = CustomArc::new(Entity { id: 0, x, y });
+    let barrier = Barrier::new(threads + 1);
+    let reading = AtomicBool::new(true);
+
+    let readers = thread::scope(|scope| {
+        let writer = race.then(|| {
+            // Raw pointers aren't `Send`; the address is.
+            let (target, reading) = (ptr::addr_of!(*shared) as usize, &reading);
+            scope.spawn(move || {
+                // Deliberately undefined behaviour, see
+                // `stress_arc_reads_racing`.
+                let target = target as *mut Entity;
+                let mut state = 0;
+                while reading.load(Ordering::Relaxed) {
+                    state ^= 1;
+                    let (x, y) = STRESSED[state];
+                    unsafe {
+                        ptr::addr_of_mut!((*target).x).write_volatile(x);
+                        ptr::addr_of_mut!((*target).y).write_volatile(y);
+                    }
+                }
+            })
+        });
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let (barrier, handle) = (&barrier, shared.clone());
+                scope.spawn(move || {
+                    barrier.wait();
+                    let mut seen = ReaderChecksum {
+                        reads: reads_per_thread,
+                        checksum: 0.0,
+                        torn: 0,
+                    };
+                    for _ in 0..reads_per_thread {
+                        let cloned_rc = handle.clone();
+                        let entity = black_box(&*cloned_rc);
+                        let (x, y) = (entity.x, entity.y);
+                        seen.checksum += (x + y) as f64;
+                        seen.torn += usize::from(!STRESSED.contains(&(x, y)));
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        barrier.wait();
+        let readers: Vec<_> = workers
+            .into_iter()
+            .map(|worker| worker.join().expect("stress reader panicked"))
+            .collect();
+        reading.store(false, Ordering::Relaxed);
+        if let Some(writer) = writer {
+            writer.join().expect("stress writer panicked");
+        }
+        readers
+    });
+
+    if let Some((index, reader)) = readers
+        .iter()
+        .enumerate()
+        .find(|(_, reader)| reader.torn > 0 || reader.checksum != reader.reads as f64 * 3.0)
+    {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "CustomArc reader {} saw {} torn reads of {} (checksum {}, expected {})",
+            index,
+            reader.torn,
+            reader.reads,
+            reader.checksum,
+            reader.reads as f64 * 3.0
+        )));
+    }
+    if shared.strong_count() != 1 {
+        return Err(BenchError::CrossCheckFailed(format!(
+            "CustomArc kept strong count {} after every reader dropped its handle",
+            shared.strong_count()
+        )));
+    }
+    Ok(readers)
+}
+
+/// Runs `stress_arc_reads` with `threads` readers doing
+/// `frames * operations_per_frame` reads each, and prints every reader's
+/// checksum.
+pub fn run_arc_stress(config: &Config, threads: usize) -> Result<(), BenchError> {
+    if config.stress_arc_race && !cfg!(feature = "race-demo") {
+        return Err(BenchError::InvalidArgument(
+            "--stress-arc-race (build with --features race-demo)".to_string(),
+        ));
+    }
+    let reads_per_thread = total_operations(config.num_frames, config.operations_per_frame, 1)?;
+    println!(
+        "Stressing CustomArc reads on {} threads{} ({} reads each)...",
+        threads,
+        if config.stress_arc_race {
+            ", racing a writer"
+        } else {
+            ""
+        },
+        reads_per_thread
+    );
+    #[cfg(feature = "race-demo")]
+    let readers = if config.stress_arc_race {
+        // SAFETY: not met; `--stress-arc-race` asks for the race on purpose.
+        unsafe { stress_arc_reads_racing(threads, reads_per_thread)? }
+    } else {
+        stress_arc_reads(threads, reads_per_thread)?
+    };
+    #[cfg(not(feature = "race-demo"))]
+    let readers = stress_arc_reads(threads, reads_per_thread)?;
+    for (index, reader) in readers.iter().enumerate() {
+        println!(
+            "reader {:<3} checksum {} over {} reads, none torn",
+            index, reader.checksum, reader.reads
+        );
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stressed_reads_see_no_torn_values_and_restore_the_count() {
+        let readers = stress_arc_reads(2, 200).unwrap();
+        assert_eq!(readers.len(), 2);
+        for reader in readers {
+            assert_eq!(reader.reads, 200);
+            assert_eq!(reader.torn, 0);
+            assert_eq!(reader.checksum, 600.0);
+        }
+    }
+}
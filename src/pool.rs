@@ -0,0 +1,206 @@
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::{RefCell, UnsafeCell};
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use crate::entity::Entity;
+use crate::rc::{Constructor, CountedRc};
+
+// ========================
+// Recycle Freed Allocations
+// ========================
+
+/// A pooled block, linked through its first word while it's free.
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+/// Free blocks of one layout.
+struct FreeList {
+    layout: Layout,
+    head: *mut FreeBlock,
+}
+
+/// The calling thread's free lists, one per layout that has been pooled.
+/// There are only ever a handful, so they're searched linearly.
+struct Pool {
+    lists: Vec<FreeList>,
+}
+
+impl Pool {
+    fn pop(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let list = self.lists.iter_mut().find(|list| list.layout == layout)?;
+        let block = NonNull::new(list.head)?;
+        list.head = unsafe { block.as_ref() }.next;
+        Some(block.cast())
+    }
+
+    fn push(&mut self, layout: Layout, block: NonNull<u8>) {
+        let index = match self.lists.iter().position(|list| list.layout == layout) {
+            Some(index) => index,
+            None => {
+                self.lists.push(FreeList {
+                    layout,
+                    head: ptr::null_mut(),
+                });
+                self.lists.len() - 1
+            }
+        };
+        let list = &mut self.lists[index];
+        let block = block.cast::<FreeBlock>().as_ptr();
+        unsafe { block.write(FreeBlock { next: list.head }) };
+        list.head = block;
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for list in &self.lists {
+            let mut block = list.head;
+            while !block.is_null() {
+                unsafe {
+                    let next = (*block).next;
+                    dealloc(block.cast(), list.layout);
+                    block = next;
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = const { RefCell::new(Pool { lists: Vec::new() }) };
+}
+
+// ========================
+// Implement PooledRc
+// ========================
+
+struct PooledRcInner<T> {
+    strong: UnsafeCell<usize>,
+    value: T,
+}
+
+/// A `CustomRc` without weaks whose allocations are recycled: when the last
+/// handle drops, the block goes onto a thread-local free list instead of back
+/// to the allocator, and `new` takes from that list first.
+///
+/// Like `CustomRc` it is single-threaded only, and since the pool is per
+/// thread, a block can only ever be reused on the thread that freed it.
+/// Pooled blocks are released when the thread exits.
+pub struct PooledRc<T> {
+    ptr: NonNull<PooledRcInner<T>>,
+}
+
+impl<T> PooledRc<T> {
+    const LAYOUT: Layout = Layout::new::<PooledRcInner<T>>();
+
+    /// Creates a new PooledRc, reusing a pooled block if there is one.
+    pub fn new(value: T) -> Self {
+        let block = POOL
+            .try_with(|pool| pool.borrow_mut().pop(Self::LAYOUT))
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| {
+                let block = unsafe { alloc(Self::LAYOUT) };
+                NonNull::new(block).unwrap_or_else(|| handle_alloc_error(Self::LAYOUT))
+            });
+        let ptr = block.cast::<PooledRcInner<T>>();
+        unsafe {
+            ptr.as_ptr().write(PooledRcInner {
+                strong: UnsafeCell::new(1),
+                value,
+            })
+        };
+        PooledRc { ptr }
+    }
+
+    /// Number of strong handles to this allocation.
+    pub fn strong_count(this: &Self) -> usize {
+        unsafe { *this.ptr.as_ref().strong.get() }
+    }
+
+    /// Address of the value, to see whether two handles share a block.
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).value) }
+    }
+}
+
+impl<T> Clone for PooledRc<T> {
+    fn clone(&self) -> Self {
+        unsafe { *self.ptr.as_ref().strong.get() += 1 };
+        PooledRc { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for PooledRc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &self.ptr.as_ref().value }
+    }
+}
+
+impl<T> Drop for PooledRc<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let strong = self.ptr.as_ref().strong.get();
+            *strong -= 1;
+            if *strong != 0 {
+                return;
+            }
+            // Drop the value before touching the pool: it may drop other
+            // PooledRcs, which pool their own blocks.
+            ptr::drop_in_place(ptr::addr_of_mut!((*self.ptr.as_ptr()).value));
+        }
+        let block = self.ptr.cast::<u8>();
+        let pooled = POOL.try_with(|pool| pool.borrow_mut().push(Self::LAYOUT, block));
+        if pooled.is_err() {
+            // The thread's pool is already gone.
+            unsafe { dealloc(block.as_ptr(), Self::LAYOUT) };
+        }
+    }
+}
+
+impl<T> Constructor<T> for PooledRc<T> {
+    fn new(value: T) -> Self {
+        PooledRc::new(value)
+    }
+}
+
+impl CountedRc<Entity> for PooledRc<Entity> {
+    fn strong_count(&self) -> usize {
+        PooledRc::strong_count(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(id: usize) -> Entity {
+        Entity { id, x: 0.0, y: 0.0 }
+    }
+
+    #[test]
+    fn despawn_then_spawn_reuses_the_block() {
+        let first = PooledRc::new(entity(0));
+        let address = PooledRc::as_ptr(&first);
+        drop(first);
+        let second = PooledRc::new(entity(1));
+        assert_eq!(PooledRc::as_ptr(&second), address);
+        assert_eq!(second.id, 1);
+    }
+
+    #[test]
+    fn a_live_clone_keeps_the_block_out_of_the_pool() {
+        let first = PooledRc::new(entity(0));
+        let clone = first.clone();
+        let address = PooledRc::as_ptr(&first);
+        drop(first);
+        let second = PooledRc::new(entity(1));
+        assert!(PooledRc::as_ptr(&second) != address);
+        assert_eq!((clone.id, PooledRc::strong_count(&clone)), (0, 1));
+        drop(clone);
+        assert_eq!(PooledRc::as_ptr(&PooledRc::new(entity(2))), address);
+    }
+}
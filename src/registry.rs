@@ -0,0 +1,79 @@
+//! A lookup table of entities owned elsewhere, the way a game keeps weak
+//! references to things it doesn't control the lifetime of.
+
+use crate::entity::Entity;
+use crate::rc::{CustomRc, CustomWeak};
+
+// ========================
+// Track Entities Weakly
+// ========================
+
+/// Weak handles to entities. An entry whose entity has been dropped stays
+/// (holding on to its allocation) until `prune` removes it.
+#[derive(Default)]
+pub struct EntityRegistry {
+    entries: Vec<CustomWeak<Entity>>,
+}
+
+impl EntityRegistry {
+    pub fn new() -> Self {
+        EntityRegistry::default()
+    }
+
+    /// Adds a weak handle to `entity`.
+    pub fn insert(&mut self, entity: &CustomRc<Entity>) {
+        self.entries.push(CustomRc::downgrade(entity));
+    }
+
+    /// Number of entries, dead ones included.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries whose entity is gone, i.e. that `prune` would
+    /// remove. Reads the counts without upgrading anything.
+    pub fn dead_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|weak| weak.strong_count() == 0)
+            .count()
+    }
+
+    /// Removes the dead entries, freeing their allocations, and returns how
+    /// many there were.
+    pub fn prune(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|weak| weak.strong_count() != 0);
+        before - self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_removes_exactly_the_dead_entries() {
+        let entities: Vec<_> = (0..4)
+            .map(|id| CustomRc::new(Entity { id, x: 0.0, y: 0.0 }))
+            .collect();
+        let mut registry = EntityRegistry::new();
+        for entity in &entities {
+            registry.insert(entity);
+        }
+        let mut alive = entities.into_iter();
+        let kept = [alive.next().unwrap(), alive.nth(1).unwrap()];
+        drop(alive);
+        assert_eq!((registry.len(), registry.dead_count()), (4, 2));
+        assert_eq!(registry.prune(), 2);
+        assert_eq!((registry.len(), registry.dead_count()), (2, 0));
+        assert_eq!(registry.prune(), 0);
+        drop(kept);
+        assert_eq!(registry.prune(), 2);
+        assert!(registry.is_empty());
+    }
+}
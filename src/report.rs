@@ -0,0 +1,423 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::config::{Config, Implementation, OutputFormat};
+
+// ========================
+// Summarize Samples
+// ========================
+
+/// `duration` in nanoseconds per operation, so runs doing different amounts
+/// of work compare directly. No operations count as one.
+pub fn ns_per_operation(duration: Duration, operations: usize) -> f64 {
+    duration.as_nanos() as f64 / operations.max(1) as f64
+}
+
+/// The middle sample, or the mean of the two middle ones for an even count.
+/// Zero for no samples.
+pub fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort_unstable();
+    let middle = samples.len() / 2;
+    match samples.len() {
+        0 => Duration::ZERO,
+        len if len % 2 == 1 => samples[middle],
+        _ => (samples[middle - 1] + samples[middle]) / 2,
+    }
+}
+
+/// Estimated relative standard error of the median of `samples`: the mean
+/// absolute deviation from the median, scaled to a normal standard deviation
+/// and then to the median's standard error (x1.2533 each, over sqrt(n)),
+/// over the median. Infinite with fewer than two samples or a zero median.
+///
+/// The median absolute deviation would be more robust, but it is zero as
+/// soon as most samples tie, which bimodal timings do all the time.
+pub fn median_relative_error(samples: &[Duration]) -> f64 {
+    let center = median(samples.to_vec());
+    if samples.len() < 2 || center.is_zero() {
+        return f64::INFINITY;
+    }
+    let n = samples.len() as f64;
+    let deviation = samples
+        .iter()
+        .map(|&sample| sample.abs_diff(center).as_secs_f64())
+        .sum::<f64>()
+        / n;
+    1.2533 * 1.2533 * deviation / n.sqrt() / center.as_secs_f64()
+}
+
+/// Samples collected by `sample_until_stable`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StableSamples {
+    pub samples: Vec<Duration>,
+    pub median: Duration,
+    /// `median_relative_error` of `samples`.
+    pub relative_error: f64,
+    /// Whether `relative_error` met the target before the sample limit.
+    pub converged: bool,
+}
+
+/// Samples never judged stable on their own; fewer make the deviation
+/// meaningless.
+const MIN_STABLE_SAMPLES: usize = 5;
+
+/// Calls `sample` until the median of its results is known to within
+/// `target_relative_error` (see `median_relative_error`), or until it has
+/// been called `max_samples` times.
+pub fn sample_until_stable<E>(
+    target_relative_error: f64,
+    max_samples: usize,
+    mut sample: impl FnMut() -> Result<Duration, E>,
+) -> Result<StableSamples, E> {
+    let mut samples = Vec::new();
+    let mut relative_error = f64::INFINITY;
+    while samples.len() < max_samples {
+        samples.push(sample()?);
+        if samples.len() < MIN_STABLE_SAMPLES.min(max_samples) {
+            continue;
+        }
+        relative_error = median_relative_error(&samples);
+        if relative_error <= target_relative_error {
+            break;
+        }
+    }
+    Ok(StableSamples {
+        median: median(samples.clone()),
+        converged: relative_error <= target_relative_error,
+        relative_error,
+        samples,
+    })
+}
+
+// ========================
+// Render the Benchmark Matrix
+// ========================
+
+/// One implementation's median run time at each entity count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixRow {
+    pub implementation: Implementation,
+    pub medians: Vec<Duration>,
+}
+
+/// Median run times of a sweep: a row per implementation and a column per
+/// entity count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matrix {
+    pub entity_counts: Vec<usize>,
+    /// Logical operations of one run at each entity count.
+    pub operations: Vec<usize>,
+    pub rows: Vec<MatrixRow>,
+}
+
+impl Matrix {
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_text(),
+            OutputFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    /// Aligned columns of median nanoseconds, headed by the entity counts,
+    /// with each row's nanoseconds per operation under it.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{:<12}", "entities");
+        for count in &self.entity_counts {
+            let _ = write!(text, " {:>14}", count);
+        }
+        text.push('\n');
+        for row in &self.rows {
+            let _ = write!(text, "{:<12}", row.implementation.name());
+            for median in &row.medians {
+                let _ = write!(text, " {:>14}", median.as_nanos());
+            }
+            let _ = write!(text, "\n{:<12}", "  ns/op");
+            for (&median, &operations) in row.medians.iter().zip(&self.operations) {
+                let _ = write!(text, " {:>14.3}", ns_per_operation(median, operations));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// A GitHub-flavored markdown table of median nanoseconds, each with its
+    /// nanoseconds per operation.
+    pub fn to_markdown(&self) -> String {
+        let mut text = String::from("| Implementation |");
+        for count in &self.entity_counts {
+            let _ = write!(text, " {} entities (ns, ns/op) |", count);
+        }
+        text.push_str("\n| --- |");
+        for _ in &self.entity_counts {
+            text.push_str(" ---: |");
+        }
+        text.push('\n');
+        for row in &self.rows {
+            let _ = write!(text, "| {} |", row.implementation.name());
+            for (&median, &operations) in row.medians.iter().zip(&self.operations) {
+                let _ = write!(
+                    text,
+                    " {} / {:.3} |",
+                    median.as_nanos(),
+                    ns_per_operation(median, operations)
+                );
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// ========================
+// Stamp Every Run
+// ========================
+
+/// What a run was configured with and built for, so that its results can
+/// be reproduced (or distrusted) once they've been shared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunStamp {
+    pub version: &'static str,
+    /// `"debug"` or `"release"`, going by `debug_assertions`.
+    pub profile: &'static str,
+    /// `arch-os[-env]` of the build.
+    pub target: String,
+    pub allocator: &'static str,
+    /// One count, or every count of a sweep.
+    pub entity_counts: Vec<usize>,
+    pub frames: usize,
+    pub operations_per_frame: usize,
+    pub implementations: Vec<Implementation>,
+}
+
+impl RunStamp {
+    /// Stamps a run of `config` with the build it runs in and the global
+    /// `allocator`'s name.
+    pub fn new(config: &Config, allocator: &'static str) -> Self {
+        RunStamp {
+            version: env!("CARGO_PKG_VERSION"),
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            target: build_target(),
+            allocator,
+            entity_counts: config.entity_counts(),
+            frames: config.num_frames,
+            operations_per_frame: config.operations_per_frame,
+            implementations: config.implementations.clone(),
+        }
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_text(),
+            OutputFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    /// Two lines: the build, then the configuration.
+    pub fn to_text(&self) -> String {
+        format!(
+            "rc-playground {} ({}, {}, {})\n{} entities, {} frames, {} ops/frame: {}\n\n",
+            self.version,
+            self.profile,
+            self.target,
+            self.allocator,
+            self.entity_count_list(),
+            self.frames,
+            self.operations_per_frame,
+            self.implementation_names()
+        )
+    }
+
+    /// A two-column markdown table, one setting per row.
+    pub fn to_markdown(&self) -> String {
+        let mut text = String::from("| Setting | Value |\n| --- | --- |\n");
+        let settings = [
+            ("version", self.version.to_string()),
+            ("profile", self.profile.to_string()),
+            ("target", self.target.clone()),
+            ("allocator", self.allocator.to_string()),
+            ("entities", self.entity_count_list()),
+            ("frames", self.frames.to_string()),
+            ("ops/frame", self.operations_per_frame.to_string()),
+            ("implementations", self.implementation_names()),
+        ];
+        for (setting, value) in settings {
+            let _ = writeln!(text, "| {} | {} |", setting, value);
+        }
+        text.push('\n');
+        text
+    }
+
+    fn entity_count_list(&self) -> String {
+        self.entity_counts
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn implementation_names(&self) -> String {
+        self.implementations
+            .iter()
+            .map(|implementation| implementation.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn build_target() -> String {
+    let env = if cfg!(target_env = "gnu") {
+        "-gnu"
+    } else if cfg!(target_env = "musl") {
+        "-musl"
+    } else if cfg!(target_env = "msvc") {
+        "-msvc"
+    } else {
+        ""
+    };
+    format!("{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix() -> Matrix {
+        Matrix {
+            entity_counts: vec![10, 100],
+            operations: vec![20, 200],
+            rows: vec![
+                MatrixRow {
+                    implementation: Implementation::StdRc,
+                    medians: vec![Duration::from_nanos(40), Duration::from_nanos(500)],
+                },
+                MatrixRow {
+                    implementation: Implementation::CustomRc,
+                    medians: vec![Duration::from_nanos(30), Duration::from_nanos(300)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn the_markdown_matrix_has_a_row_per_implementation_and_a_column_per_count() {
+        let markdown = matrix().render(OutputFormat::Markdown);
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "| Implementation | 10 entities (ns, ns/op) | 100 entities (ns, ns/op) |",
+                "| --- | ---: | ---: |",
+                "| StdRc | 40 / 2.000 | 500 / 2.500 |",
+                "| CustomRc | 30 / 1.500 | 300 / 1.500 |",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_text_matrix_has_two_lines_per_implementation() {
+        let text = matrix().render(OutputFormat::Text);
+        assert_eq!(text.lines().count(), 1 + 2 * 2);
+        assert!(text.lines().next().unwrap().starts_with("entities"));
+    }
+
+    #[test]
+    fn the_median_of_an_even_count_is_the_mean_of_the_middle_two() {
+        let ms = Duration::from_millis;
+        assert_eq!(median(vec![]), Duration::ZERO);
+        assert_eq!(median(vec![ms(3), ms(1), ms(2)]), ms(2));
+        assert_eq!(median(vec![ms(4), ms(1), ms(2), ms(9)]), ms(3));
+        assert_eq!(median_relative_error(&[ms(1)]), f64::INFINITY);
+    }
+
+    #[test]
+    fn steady_samples_stop_at_the_minimum() {
+        let mut calls = 0;
+        let stable = sample_until_stable::<()>(0.01, 100, || {
+            calls += 1;
+            Ok(Duration::from_millis(5))
+        })
+        .unwrap();
+        assert_eq!(calls, MIN_STABLE_SAMPLES);
+        assert!(stable.converged);
+        assert_eq!(stable.median, Duration::from_millis(5));
+        assert_eq!(stable.relative_error, 0.0);
+    }
+
+    #[test]
+    fn noisy_samples_stop_at_the_limit_unconverged() {
+        let mut calls = 0u64;
+        let stable = sample_until_stable::<()>(0.001, 20, || {
+            calls += 1;
+            Ok(Duration::from_millis(if calls.is_multiple_of(2) {
+                1
+            } else {
+                100
+            }))
+        })
+        .unwrap();
+        assert_eq!(stable.samples.len(), 20);
+        assert!(!stable.converged);
+        assert!(stable.relative_error > 0.001);
+    }
+
+    #[test]
+    fn a_failing_sample_stops_sampling() {
+        let mut calls = 0;
+        let result = sample_until_stable(0.01, 100, || {
+            calls += 1;
+            if calls == 3 {
+                Err("broken")
+            } else {
+                Ok(Duration::from_millis(1))
+            }
+        });
+        assert_eq!(result, Err("broken"));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn the_stamp_records_the_build_and_the_configuration() {
+        let config = Config {
+            num_entities: 12,
+            num_frames: 3,
+            operations_per_frame: 4,
+            implementations: vec![Implementation::StdRc, Implementation::CustomRc],
+            ..Config::default()
+        };
+        let stamp = RunStamp::new(&config, "system");
+        let profile = if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        };
+        assert_eq!(stamp.profile, profile);
+        assert!(stamp.target.starts_with(std::env::consts::ARCH));
+        assert_eq!(
+            stamp.to_text().lines().nth(1),
+            Some("12 entities, 3 frames, 4 ops/frame: StdRc, CustomRc")
+        );
+        let markdown = stamp.render(OutputFormat::Markdown);
+        assert!(markdown.contains(&format!("| profile | {} |", profile)));
+        assert!(markdown.contains("| allocator | system |"));
+
+        let sweep = RunStamp::new(
+            &Config {
+                sweep_entities: vec![10, 20],
+                ..config
+            },
+            "system",
+        );
+        assert!(sweep.to_text().contains("10,20 entities"));
+    }
+
+    #[test]
+    fn ns_per_operation_divides_by_the_logical_operations() {
+        let operations = crate::config::total_operations(10, 5, 100).unwrap();
+        assert_eq!(ns_per_operation(Duration::from_micros(10), operations), 2.0);
+        assert_eq!(ns_per_operation(Duration::from_nanos(7), 0), 7.0);
+    }
+}
@@ -0,0 +1,98 @@
+//! The process's peak resident set size, which also sees the allocator's
+//! own overhead and fragmentation that counting allocations can't.
+
+/// The most memory the process has had resident so far (or since
+/// `reset_peak_rss`), in bytes, or `None` without the `rusage` feature (or
+/// off Unix).
+///
+/// On Linux this is `VmHWM`: `getrusage`'s `ru_maxrss` there also counts
+/// the peak of whatever ran before `exec` (cargo, say) and ignores resets.
+#[cfg(all(feature = "rusage", target_os = "linux"))]
+pub fn peak_rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: usize = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    kilobytes.checked_mul(1024)
+}
+
+#[cfg(all(feature = "rusage", unix, not(target_os = "linux")))]
+pub fn peak_rss() -> Option<usize> {
+    use std::ffi::{c_int, c_long};
+
+    // `struct rusage`: two `timeval`s, then 14 longs starting with `ru_maxrss`.
+    #[repr(C)]
+    struct Rusage {
+        times: [c_long; 4],
+        max_rss: c_long,
+        rest: [c_long; 13],
+    }
+
+    extern "C" {
+        fn getrusage(who: c_int, usage: *mut Rusage) -> c_int;
+    }
+
+    const RUSAGE_SELF: c_int = 0;
+    let mut usage = Rusage {
+        times: [0; 4],
+        max_rss: 0,
+        rest: [0; 13],
+    };
+    if unsafe { getrusage(RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let max_rss = usize::try_from(usage.max_rss).ok()?;
+    // macOS reports bytes, the BSDs kilobytes.
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        max_rss.checked_mul(1024)
+    }
+}
+
+#[cfg(not(all(feature = "rusage", unix)))]
+pub fn peak_rss() -> Option<usize> {
+    None
+}
+
+/// Lowers the peak RSS back to the current RSS, where the kernel allows it
+/// (Linux), so the next `peak_rss` is the peak since this call rather than
+/// since the process started. Returns whether it did.
+#[cfg(all(feature = "rusage", target_os = "linux"))]
+pub fn reset_peak_rss() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+#[cfg(not(all(feature = "rusage", target_os = "linux")))]
+pub fn reset_peak_rss() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "rusage", target_os = "linux"))]
+    #[test]
+    fn the_peak_covers_memory_still_resident() {
+        const BYTES: usize = 16 << 20;
+        let buffer = std::hint::black_box(vec![1u8; BYTES]);
+        assert!(peak_rss().unwrap() >= BYTES);
+        if reset_peak_rss() {
+            assert!(peak_rss().unwrap() >= BYTES);
+        }
+        drop(buffer);
+    }
+
+    #[cfg(not(feature = "rusage"))]
+    #[test]
+    fn without_rusage_there_is_no_peak() {
+        assert_eq!(peak_rss(), None);
+        assert!(!reset_peak_rss());
+    }
+}
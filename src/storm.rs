@@ -0,0 +1,129 @@
+//! Readers holding weak handles to entities that keep being despawned and
+//! respawned under them, the way event subscribers outlive what they
+//! subscribed to.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::config::{total_operations, Config};
+use crate::entity::Entity;
+use crate::error::BenchError;
+use crate::rc::{CustomRc, StdRcWrapper, WeakRc};
+use crate::report::ns_per_operation;
+use crate::rng::SplitMix64;
+
+// ========================
+// Upgrade Storm
+// ========================
+
+/// What `upgrade_storm` saw.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpgradeStorm {
+    pub upgrades: usize,
+    /// Upgrades that found their entity alive.
+    pub succeeded: usize,
+    /// Time spent upgrading (and reading through what upgraded), alone.
+    pub duration: Duration,
+}
+
+impl UpgradeStorm {
+    /// Share of the upgrades that succeeded.
+    pub fn success_rate(&self) -> f64 {
+        self.succeeded as f64 / self.upgrades.max(1) as f64
+    }
+
+    /// Nanoseconds per upgrade, failed or not.
+    pub fn per_upgrade(&self) -> f64 {
+        ns_per_operation(self.duration, self.upgrades)
+    }
+}
+
+/// Keeps one weak to each of `num_entities` entities. Every frame despawns
+/// a random `despawn_fraction` of them, upgrades every weak
+/// `operations_per_frame` times, reading through the ones that succeed,
+/// then respawns the dead entities and points their weaks at the new ones.
+/// Only the upgrading is timed.
+pub fn upgrade_storm<RcType>(
+    config: &Config,
+    despawn_fraction: f64,
+) -> Result<UpgradeStorm, BenchError>
+where
+    RcType: WeakRc<Entity>,
+{
+    let upgrades = total_operations(
+        config.num_frames,
+        config.operations_per_frame,
+        config.num_entities,
+    )?;
+    let spawn = |id: usize| RcType::new(Entity { id, x: 1.0, y: 2.0 });
+    let mut strongs: Vec<Option<RcType>> =
+        (0..config.num_entities).map(|id| Some(spawn(id))).collect();
+    let mut weaks: Vec<RcType::Weak> = strongs.iter().flatten().map(RcType::downgrade).collect();
+    let despawns = (config.num_entities as f64 * despawn_fraction.clamp(0.0, 1.0)) as usize;
+    let mut rng = SplitMix64::new(config.seed);
+    let mut storm = UpgradeStorm {
+        upgrades,
+        ..UpgradeStorm::default()
+    };
+    for _ in 0..config.num_frames {
+        // Picks can repeat, so slightly fewer than `despawns` may die.
+        for _ in 0..despawns {
+            let index = rng.below(strongs.len());
+            strongs[index] = None;
+        }
+
+        let start = Instant::now();
+        for _ in 0..config.operations_per_frame {
+            for weak in &weaks {
+                if let Some(entity_rc) = RcType::upgrade(weak) {
+                    black_box(entity_rc.x + entity_rc.y);
+                    storm.succeeded += 1;
+                }
+            }
+        }
+        storm.duration += start.elapsed();
+
+        for (id, (strong, weak)) in strongs.iter_mut().zip(&mut weaks).enumerate() {
+            if strong.is_none() {
+                let entity_rc = spawn(id);
+                *weak = entity_rc.downgrade();
+                *strong = Some(entity_rc);
+            }
+        }
+    }
+    Ok(storm)
+}
+
+/// Runs `upgrade_storm` for `StdRc` and `CustomRc` and prints the upgrade
+/// latency and success rate of each.
+pub fn run_upgrade_storm_benchmark(
+    config: &Config,
+    despawn_fraction: f64,
+) -> Result<(), BenchError> {
+    println!(
+        "Weak upgrade storm, {:.0}% of entities despawned per frame...",
+        despawn_fraction * 100.0
+    );
+    for (name, storm) in [
+        (
+            "StdRc",
+            upgrade_storm::<StdRcWrapper<Entity>>(config, despawn_fraction)?,
+        ),
+        (
+            "CustomRc",
+            upgrade_storm::<CustomRc<Entity>>(config, despawn_fraction)?,
+        ),
+    ] {
+        println!(
+            "{:<10} {:?} ({:.2} ns/upgrade), {:.1}% succeeded ({} of {})",
+            name,
+            storm.duration,
+            storm.per_upgrade(),
+            storm.success_rate() * 100.0,
+            storm.succeeded,
+            storm.upgrades
+        );
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,325 @@
+//! Chrome's trace_event JSON (what `chrome://tracing` and Perfetto open) of
+//! the phases every benchmarked implementation went through.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::bench::BenchResult;
+
+/// Process and thread id of the single "benchmark" track.
+const TRACK: u32 = 1;
+
+struct TraceEvent {
+    name: String,
+    begin: bool,
+    at: Duration,
+}
+
+/// Begin/end events for each result's warm-up, setup, run, dependent pass
+/// and teardown, nested in one event per implementation.
+///
+/// Only the durations are measured: the phases are laid end to end, so
+/// whatever ran between them (balance checks, printing) is left out and a
+/// timestamp is how far into the timed work it falls.
+#[derive(Default)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+    now: Duration,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    pub fn from_results(results: &[BenchResult]) -> Self {
+        let mut trace = Trace::new();
+        for result in results {
+            trace.push(result);
+        }
+        trace
+    }
+
+    /// Appends `result`'s phases after everything already traced.
+    pub fn push(&mut self, result: &BenchResult) {
+        self.mark(&result.name, true);
+        if let Some(warm_up) = result.warm_up {
+            self.span("warm-up", warm_up);
+        }
+        self.span("setup", result.setup);
+        self.span("run", result.run);
+        if let Some(dependent) = result.dependent {
+            self.span("dependent access", dependent);
+        }
+        self.span("teardown", result.teardown);
+        self.mark(&result.name, false);
+    }
+
+    fn span(&mut self, name: &str, duration: Duration) {
+        self.mark(name, true);
+        self.now += duration;
+        self.mark(name, false);
+    }
+
+    fn mark(&mut self, name: &str, begin: bool) {
+        self.events.push(TraceEvent {
+            name: name.to_string(),
+            begin,
+            at: self.now,
+        });
+    }
+
+    /// The trace as a JSON object with a `traceEvents` array, in
+    /// microseconds as the format wants.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[\n");
+        let _ = write!(
+            json,
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{0},\"tid\":{0},\"args\":{{\"name\":\"benchmark\"}}}}",
+            TRACK
+        );
+        for event in &self.events {
+            let _ = write!(
+                json,
+                ",\n{{\"name\":\"{}\",\"ph\":\"{}\",\"ts\":{:.3},\"pid\":{3},\"tid\":{3}}}",
+                escape(&event.name),
+                if event.begin { "B" } else { "E" },
+                event.at.as_nanos() as f64 / 1000.0,
+                TRACK
+            );
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+/// `text` as the contents of a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::Bencher;
+    use crate::clock::MockClock;
+    use crate::config::{Config, Implementation};
+
+    /// Just enough JSON to read a trace back: a malformed one panics.
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    impl Json {
+        fn get(&self, key: &str) -> Option<&Json> {
+            match self {
+                Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        fn str(&self, key: &str) -> Option<&str> {
+            match self.get(key) {
+                Some(Json::String(text)) => Some(text),
+                _ => None,
+            }
+        }
+    }
+
+    fn parse(json: &str) -> Json {
+        let mut chars = json.chars().peekable();
+        let value = parse_value(&mut chars);
+        skip_whitespace(&mut chars);
+        assert_eq!(chars.next(), None, "trailing text");
+        value
+    }
+
+    type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+    fn skip_whitespace(chars: &mut Chars) {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(chars: &mut Chars, expected: char) {
+        skip_whitespace(chars);
+        assert_eq!(chars.next(), Some(expected));
+    }
+
+    /// The items of an array or object up to `close`, comma separated.
+    fn parse_items(chars: &mut Chars, close: char, mut item: impl FnMut(&mut Chars)) {
+        skip_whitespace(chars);
+        if chars.next_if_eq(&close).is_some() {
+            return;
+        }
+        loop {
+            item(chars);
+            skip_whitespace(chars);
+            match chars.next() {
+                Some(',') => {}
+                Some(c) if c == close => return,
+                other => panic!("expected ',' or {:?}, found {:?}", close, other),
+            }
+        }
+    }
+
+    fn parse_value(chars: &mut Chars) -> Json {
+        skip_whitespace(chars);
+        match chars.peek() {
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                parse_items(chars, '}', |chars| {
+                    skip_whitespace(chars);
+                    let key = parse_string(chars);
+                    expect(chars, ':');
+                    fields.push((key, parse_value(chars)));
+                });
+                Json::Object(fields)
+            }
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                parse_items(chars, ']', |chars| items.push(parse_value(chars)));
+                Json::Array(items)
+            }
+            Some('"') => Json::String(parse_string(chars)),
+            _ => {
+                let mut number = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    number.push(c);
+                }
+                Json::Number(
+                    number
+                        .parse()
+                        .unwrap_or_else(|_| panic!("bad number {:?}", number)),
+                )
+            }
+        }
+    }
+
+    fn parse_string(chars: &mut Chars) -> String {
+        assert_eq!(chars.next(), Some('"'));
+        let mut text = String::new();
+        loop {
+            match chars.next().expect("unterminated string") {
+                '"' => return text,
+                '\\' => match chars.next() {
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        text.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                    }
+                    Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                    other => panic!("bad escape {:?}", other),
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    #[test]
+    fn every_phase_gets_a_begin_and_an_end() {
+        let bencher = Bencher::new(Config {
+            num_entities: 8,
+            num_frames: 2,
+            operations_per_frame: 2,
+            quiet: true,
+            ..Config::default()
+        })
+        .clock(MockClock::new(Duration::from_micros(5)));
+        let results = [Implementation::StdRc, Implementation::CustomRc]
+            .map(|implementation| bencher.run(implementation).unwrap());
+        let trace = parse(&Trace::from_results(&results).to_json());
+        let Json::Object(fields) = &trace else {
+            panic!("not an object: {:?}", trace);
+        };
+        assert_eq!(fields.len(), 1);
+        let Some(Json::Array(events)) = trace.get("traceEvents") else {
+            panic!("no traceEvents array");
+        };
+        assert_eq!(events[0].str("ph"), Some("M"));
+        let events = &events[1..];
+
+        // Every begin is closed by a matching end, in order.
+        let mut open = Vec::new();
+        let mut ends = Vec::new();
+        for event in events {
+            let name = event.str("name").unwrap();
+            let Some(&Json::Number(ts)) = event.get("ts") else {
+                panic!("no ts: {:?}", event);
+            };
+            assert_eq!(event.get("pid"), Some(&Json::Number(TRACK.into())));
+            match event.str("ph") {
+                Some("B") => open.push(name),
+                Some("E") => {
+                    assert_eq!(open.pop(), Some(name));
+                    ends.push((name, ts));
+                }
+                other => panic!("unexpected phase {:?}", other),
+            }
+        }
+        assert!(open.is_empty(), "left open: {:?}", open);
+        let count = |name: &str| ends.iter().filter(|&&(end, _)| end == name).count();
+        for name in ["StdRc", "CustomRc"] {
+            assert_eq!(count(name), 1, "{}", name);
+        }
+        for name in ["warm-up", "setup", "run", "teardown"] {
+            assert_eq!(count(name), 2, "{}", name);
+        }
+        assert_eq!(count("dependent access"), 0);
+        // Each implementation's events span its phases laid end to end.
+        let std_rc = &results[0];
+        let end = std_rc.warm_up.unwrap() + std_rc.setup + std_rc.run + std_rc.teardown;
+        let std_rc_end = ends.iter().find(|&&(name, _)| name == "StdRc").unwrap().1;
+        assert!((std_rc_end - end.as_nanos() as f64 / 1000.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn the_reader_refuses_malformed_json() {
+        for malformed in [
+            "{\"a\":1,}",
+            "{\"a\":[1,2}",
+            "[1 2]",
+            "{\"a\":1}}",
+            "{\"ts\":1.2.3}",
+        ] {
+            assert!(
+                std::panic::catch_unwind(|| parse(malformed)).is_err(),
+                "{}",
+                malformed
+            );
+        }
+        assert_eq!(
+            parse("[1.5,\"\\u000a\"]"),
+            Json::Array(vec![Json::Number(1.5), Json::String("\n".into())])
+        );
+    }
+
+    #[test]
+    fn names_are_escaped_as_json_strings() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+}
@@ -0,0 +1,348 @@
+//! Checks that count allocations, through a global `BenchAllocator`.
+//!
+//! The allocator would see every thread's allocations, including the test
+//! harness's own, so only the thread inside `counted` goes through it; every
+//! other allocation goes straight to `System`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::mem::MaybeUninit;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use rc_playground::{
+    check_clone_allocation_free, BenchAllocator, BenchError, Bencher, Config, CustomRc, CustomWeak,
+    Entity, GameBuilder, Implementation,
+};
+
+/// Routes the counted thread's allocations to `bench`, failing those larger
+/// than `cap` bytes.
+struct TestAllocator {
+    bench: BenchAllocator,
+    cap: AtomicUsize,
+}
+
+#[global_allocator]
+static ALLOCATOR: TestAllocator = TestAllocator {
+    bench: BenchAllocator::new(),
+    cap: AtomicUsize::new(usize::MAX),
+};
+
+thread_local! {
+    static COUNTED: Cell<bool> = const { Cell::new(false) };
+}
+
+fn is_counted() -> bool {
+    COUNTED.try_with(Cell::get).unwrap_or(false)
+}
+
+unsafe impl GlobalAlloc for TestAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() > self.cap.load(Ordering::Relaxed) {
+            return std::ptr::null_mut();
+        }
+        if is_counted() {
+            self.bench.alloc(layout)
+        } else {
+            System.alloc(layout)
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if is_counted() {
+            self.bench.dealloc(ptr, layout)
+        } else {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > self.cap.load(Ordering::Relaxed) {
+            return std::ptr::null_mut();
+        }
+        if is_counted() {
+            self.bench.realloc(ptr, layout, new_size)
+        } else {
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+}
+
+static COUNTING: Mutex<()> = Mutex::new(());
+
+/// Runs `f` with this thread's allocations going through `ALLOCATOR.bench`,
+/// one test at a time, with allocations capped at `cap` bytes.
+fn counted<R>(cap: usize, f: impl FnOnce(&BenchAllocator) -> R) -> R {
+    /// Restores the defaults even if `f` panics.
+    struct Uncount;
+
+    impl Drop for Uncount {
+        fn drop(&mut self) {
+            ALLOCATOR.cap.store(usize::MAX, Ordering::Relaxed);
+            COUNTED.with(|counted| counted.set(false));
+        }
+    }
+
+    let _serial = COUNTING.lock().unwrap_or_else(PoisonError::into_inner);
+    ALLOCATOR.cap.store(cap, Ordering::Relaxed);
+    COUNTED.with(|counted| counted.set(true));
+    let _uncount = Uncount;
+    f(&ALLOCATOR.bench)
+}
+
+#[test]
+fn an_empty_weak_never_allocates_or_upgrades() {
+    let made = counted(usize::MAX, |allocator| {
+        allocator.begin_size_tracking();
+        let weak = CustomWeak::<Entity>::new();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        let copy = weak.clone();
+        drop(weak);
+        drop(copy);
+        allocator.end_size_tracking().total()
+    });
+    assert_eq!(made, 0);
+}
+
+#[test]
+fn an_uninit_slice_over_the_cap_reports_the_failure() {
+    counted(1024, |_| {
+        let fits = CustomRc::<[MaybeUninit<u64>]>::try_new_uninit_slice(16).unwrap();
+        assert_eq!(fits.len(), 16);
+        assert!(CustomRc::<[MaybeUninit<u64>]>::try_new_uninit_slice(1024).is_err());
+    });
+    // A length whose layout overflows fails before reaching the allocator.
+    assert!(CustomRc::<[MaybeUninit<u64>]>::try_new_uninit_slice(usize::MAX).is_err());
+}
+
+#[test]
+fn each_custom_rc_makes_one_allocation_of_its_inner_size() {
+    let histogram = counted(usize::MAX, |allocator| {
+        allocator.begin_size_tracking();
+        let handles: [CustomRc<Entity>; 4] =
+            std::array::from_fn(|id| CustomRc::new(Entity { id, x: 0.0, y: 0.0 }));
+        let clones = handles.clone();
+        let histogram = allocator.end_size_tracking();
+        drop(clones);
+        drop(handles);
+        histogram
+    });
+    // The strong and weak counts, then the entity; clones allocate nothing.
+    let inner = 2 * std::mem::size_of::<usize>() + std::mem::size_of::<Entity>();
+    assert_eq!(
+        histogram.buckets().collect::<Vec<_>>(),
+        [(inner.next_power_of_two(), 4)]
+    );
+}
+
+// The panics below go through `resume_unwind`, which skips the panic hook:
+// printing the message (and a backtrace) would allocate, and keep some of it.
+
+#[test]
+fn a_constructor_panicking_mid_setup_leaks_nothing() {
+    for fail_at in [0, 1, 50, 99] {
+        counted(usize::MAX, |allocator| {
+            allocator.assert_no_leaks(|| {
+                let unwound = panic::catch_unwind(AssertUnwindSafe(|| {
+                    (0..100)
+                        .map(|id| {
+                            CustomRc::allocate_with(|| {
+                                if id == fail_at {
+                                    panic::resume_unwind(Box::new(id));
+                                }
+                                Entity { id, x: 0.0, y: 0.0 }
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                }));
+                drop(unwound.err().unwrap());
+            })
+        });
+    }
+}
+
+#[test]
+fn a_factory_panicking_mid_setup_leaks_nothing() {
+    counted(usize::MAX, |allocator| {
+        allocator.assert_no_leaks(|| {
+            let unwound = panic::catch_unwind(|| {
+                GameBuilder::default()
+                    .quiet(true)
+                    .entities(100)
+                    .initial_clones_per_entity(1)
+                    .build_with_factory(&|id| {
+                        if id == 50 {
+                            panic::resume_unwind(Box::new(id));
+                        }
+                        CustomRc::new(Entity { id, x: 0.0, y: 0.0 })
+                    })
+            });
+            drop(unwound.err().unwrap());
+        })
+    });
+}
+
+#[test]
+fn dropping_the_last_custom_rc_frees_its_block() {
+    counted(usize::MAX, |allocator| {
+        allocator.assert_no_leaks(|| {
+            let rc = CustomRc::new(Entity {
+                id: 0,
+                x: 0.0,
+                y: 0.0,
+            });
+            let clone = rc.clone();
+            drop(rc);
+            drop(clone);
+        })
+    });
+}
+
+#[test]
+fn unwrapping_a_custom_rc_frees_its_block() {
+    counted(usize::MAX, |allocator| {
+        allocator.assert_no_leaks(|| {
+            let rc = CustomRc::new(Entity {
+                id: 0,
+                x: 0.0,
+                y: 0.0,
+            });
+            let clone = rc.clone();
+            let rc = CustomRc::try_unwrap(rc).err().unwrap();
+            drop(clone);
+            assert_eq!(CustomRc::try_unwrap(rc).ok().unwrap().id, 0);
+
+            let rc = CustomRc::new(Entity {
+                id: 1,
+                x: 0.0,
+                y: 0.0,
+            });
+            let clone = rc.clone();
+            assert!(CustomRc::into_inner(rc).is_none());
+            assert_eq!(CustomRc::into_inner(clone).unwrap().id, 1);
+        })
+    });
+}
+
+#[test]
+fn a_weak_outliving_its_custom_rc_frees_the_block_last() {
+    counted(usize::MAX, |allocator| {
+        allocator.assert_no_leaks(|| {
+            let rc = CustomRc::new(Entity {
+                id: 0,
+                x: 0.0,
+                y: 0.0,
+            });
+            let weak = CustomRc::downgrade(&rc);
+            let copy = weak.clone();
+            drop(rc);
+            assert!(weak.upgrade().is_none());
+            drop(weak);
+            drop(copy);
+
+            // A weak left when the value is unwrapped still owns the block.
+            let rc = CustomRc::new(Entity {
+                id: 1,
+                x: 0.0,
+                y: 0.0,
+            });
+            let weak = CustomRc::downgrade(&rc);
+            assert_eq!(CustomRc::try_unwrap(rc).ok().unwrap().id, 1);
+            drop(weak);
+        })
+    });
+}
+
+#[test]
+fn make_mut_on_a_shared_custom_rc_leaks_neither_block() {
+    counted(usize::MAX, |allocator| {
+        allocator.assert_no_leaks(|| {
+            let mut rc = CustomRc::new(Entity {
+                id: 0,
+                x: 0.0,
+                y: 0.0,
+            });
+            let clone = rc.clone();
+            CustomRc::make_mut(&mut rc).x = 1.0;
+            assert_eq!((rc.x, clone.x), (1.0, 0.0));
+            drop(clone);
+
+            // With only a weak left, the value moves out from under it.
+            let weak = CustomRc::downgrade(&rc);
+            CustomRc::make_mut(&mut rc).x = 2.0;
+            assert!(weak.upgrade().is_none());
+            drop(weak);
+            drop(rc);
+        })
+    });
+}
+
+#[test]
+fn cloning_a_custom_rc_never_allocates() {
+    assert_eq!(counted(usize::MAX, check_clone_allocation_free), Ok(()));
+    // An allocator that isn't in use can't see `make_mut`'s copy either.
+    assert!(matches!(
+        check_clone_allocation_free(&BenchAllocator::new()),
+        Err(BenchError::CrossCheckFailed(_))
+    ));
+}
+
+#[test]
+fn prewarming_allocates_blocks_the_size_of_an_entity() {
+    let inner =
+        (2 * std::mem::size_of::<usize>() + std::mem::size_of::<Entity>()).next_power_of_two();
+    let entity_blocks = |prewarm_blocks| {
+        let config = Config {
+            num_entities: 8,
+            num_frames: 1,
+            operations_per_frame: 1,
+            implementations: vec![Implementation::CustomRc],
+            prewarm_blocks,
+            alloc_histogram: true,
+            quiet: true,
+            ..Config::default()
+        };
+        let sizes = counted(usize::MAX, |_| {
+            Bencher::new(config)
+                .warm_up(false)
+                .allocator(&ALLOCATOR.bench)
+                .run(Implementation::CustomRc)
+                .unwrap()
+                .allocation_sizes
+                .unwrap()
+        });
+        let blocks = sizes
+            .buckets()
+            .find(|&(size, _)| size == inner)
+            .map_or(0, |(_, count)| count);
+        blocks
+    };
+    assert_eq!(entity_blocks(100) - entity_blocks(0), 100);
+}
+
+#[test]
+fn prewarmed_setups_take_about_as_long_as_each_other() {
+    let config = Config {
+        num_entities: 20_000,
+        num_frames: 1,
+        operations_per_frame: 1,
+        implementations: vec![Implementation::CustomRc],
+        prewarm_blocks: 20_000,
+        quiet: true,
+        ..Config::default()
+    };
+    let bencher = Bencher::new(config).warm_up(false);
+    let [first, second] = [(); 2].map(|()| bencher.run(Implementation::CustomRc).unwrap().setup);
+    let (faster, slower) = (first.min(second), first.max(second));
+    // Loose: only a cold allocator's costs should be anywhere near this,
+    // with some slack for a timer tick or a preemption on a busy machine.
+    assert!(
+        slower <= faster * 4 + Duration::from_millis(5),
+        "{:?} then {:?}",
+        first,
+        second
+    );
+}